@@ -0,0 +1,229 @@
+//! Tools for adapting the crate to new terminal variants.
+
+use std::{fmt::Write, str::FromStr};
+
+use crate::{
+    protocol::parse_id, Context, Error, FieldLayout, FieldOrder, Protocol, Result, Status, Unit,
+    WeightType,
+};
+
+/// Describes how `protocol` splits a frame into fields,
+/// one line per field with its raw content and interpreted meaning,
+/// followed by the result of parsing the whole frame.
+///
+/// This helps to find out where the offsets land if frames of an
+/// unknown terminal can't be parsed:
+///
+/// ```
+/// # use soehnle_t3005::{debug::annotate_frame, Protocol};
+/// let annotation = annotate_frame(b"001001N   3,5 kg\r\n", &Protocol::default());
+/// assert_eq!(
+///     annotation,
+///     "\
+/// status  \"0010\"  standstill
+/// id      \"01\"  1
+/// type    \"N\"  Net
+/// value   \"   3,5 kg\"  3.5 kg
+/// result  OK
+/// "
+/// );
+/// ```
+#[must_use]
+pub fn annotate_frame(bytes: &[u8], protocol: &Protocol) -> String {
+    let frame = String::from_utf8_lossy(bytes);
+    let s = protocol.normalize(&frame);
+    let mut fields = Vec::new();
+    match protocol.layout {
+        FieldLayout::Fixed => {
+            let mut rest = s.as_ref();
+            let mut take = |len: usize| {
+                let split = len.min(rest.len());
+                let split = (0..=split)
+                    .rev()
+                    .find(|i| rest.is_char_boundary(*i))
+                    .unwrap_or_default();
+                let (field, tail) = rest.split_at(split);
+                rest = tail;
+                field
+            };
+            if protocol.address_field_len > 0 {
+                fields.push(("address", take(protocol.address_field_len)));
+            }
+            match protocol.order {
+                FieldOrder::StatusFirst => {
+                    fields.push(("status", take(4)));
+                    fields.push(("id", take(2)));
+                }
+                FieldOrder::IdFirst => {
+                    fields.push(("id", take(2)));
+                    fields.push(("status", take(4)));
+                }
+            }
+            fields.push(("type", take(1)));
+            fields.push(("value", rest));
+        }
+        FieldLayout::Delimited(delimiter) => {
+            let mut names = vec!["status", "id", "value", "unit"];
+            if protocol.order == FieldOrder::IdFirst {
+                names.swap(0, 1);
+            }
+            if protocol.address_field_len > 0 {
+                names.insert(0, "address");
+            }
+            let mut values = s.split(delimiter);
+            // The unit field is optional, so only fields present in the frame are listed
+            fields.extend(names.into_iter().zip(values.by_ref()));
+            for value in values {
+                fields.push(("extra", value));
+            }
+        }
+    }
+    let mut out = String::new();
+    for (name, raw) in fields {
+        let meaning = match describe(protocol, name, raw) {
+            Ok(meaning) => meaning,
+            Err(err) => format!("error: {err}"),
+        };
+        let _ = writeln!(out, "{name:<8}{raw:?}  {meaning}");
+    }
+    let result = match protocol.parse_message(&frame) {
+        Ok(_) => "OK".to_owned(),
+        Err(err) => format!("error: {err}"),
+    };
+    let _ = writeln!(out, "result  {result}");
+    out
+}
+
+fn describe(protocol: &Protocol, name: &str, raw: &str) -> Result<String> {
+    let meaning = match name {
+        "address" => raw
+            .trim()
+            .parse::<u8>()
+            .context(Error::Address, raw)?
+            .to_string(),
+        "status" => {
+            let status = Status::from_str(raw.trim())?;
+            let flags = [
+                (status.under_load, "under load"),
+                (status.over_load, "over load"),
+                (status.standstill, "standstill"),
+                (status.empty_message, "empty message"),
+            ];
+            let flags: Vec<_> = flags
+                .iter()
+                .filter(|(set, _)| *set)
+                .map(|(_, flag)| *flag)
+                .collect();
+            if flags.is_empty() {
+                "-".to_owned()
+            } else {
+                flags.join(", ")
+            }
+        }
+        "id" => parse_id(raw.trim())?.to_string(),
+        "type" => format!("{:?}", WeightType::from_str(raw)?),
+        "value" => {
+            let (value, unit, _) = protocol.parse_value(&format!(" {raw}"))?;
+            // A delimited value without suffix takes its unit from the next field
+            if protocol.layout == FieldLayout::Fixed
+                || Unit::split_suffix(raw.trim_end()).1.is_some()
+            {
+                format!("{value} {unit}")
+            } else {
+                value.to_string()
+            }
+        }
+        "unit" => Unit::from_str(raw.trim())?.to_string(),
+        _ => "unexpected field".to_owned(),
+    };
+    Ok(meaning)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn annotate_broken_frame() {
+        let protocol = Protocol {
+            address_field_len: 2,
+            ..Protocol::default()
+        };
+        assert_eq!(
+            annotate_frame(b"071100XXB  -3,5 lb\r\n", &protocol),
+            "\
+address \"07\"  7
+status  \"1100\"  under load, over load
+id      \"XX\"  error: Invalid balance ID: 'XX'
+type    \"B\"  Gross
+value   \"  -3,5 lb\"  -3.5 lb
+result  error: Invalid balance ID: 'XX'
+"
+        );
+        assert_eq!(
+            annotate_frame(b"00", &Protocol::default()),
+            "\
+status  \"00\"  error: Invalid message length: '00'
+id      \"\"  error: Invalid balance ID: ''
+type    \"\"  error: Invalid weight type: ''
+value   \"\"  error: Empty balance value
+result  error: Invalid message length: '00'
+"
+        );
+    }
+
+    #[test]
+    fn annotate_id_first_frame() {
+        let protocol = Protocol {
+            order: FieldOrder::IdFirst,
+            ..Protocol::default()
+        };
+        assert_eq!(
+            annotate_frame(b"010010N   3,5 kg", &protocol),
+            "\
+id      \"01\"  1
+status  \"0010\"  standstill
+type    \"N\"  Net
+value   \"   3,5 kg\"  3.5 kg
+result  OK
+"
+        );
+    }
+
+    #[test]
+    fn annotate_delimited_frame() {
+        let protocol = Protocol {
+            layout: FieldLayout::Delimited(';'),
+            ..Protocol::default()
+        };
+        assert_eq!(
+            annotate_frame(b"0000;03;1,5;g;?\r\n", &protocol),
+            "\
+status  \"0000\"  -
+id      \"03\"  3
+value   \"1,5\"  1.5
+unit    \"g\"  g
+extra   \"?\"  unexpected field
+result  error: Invalid message length: '0000;03;1,5;g;?'
+"
+        );
+    }
+
+    #[test]
+    fn annotate_delimited_frame_with_unit_suffix() {
+        let protocol = Protocol {
+            layout: FieldLayout::Delimited(';'),
+            ..Protocol::default()
+        };
+        let annotation = annotate_frame(b"0000;03;1,5 g\r\n", &protocol);
+        assert_eq!(
+            annotation,
+            "\
+status  \"0000\"  -
+id      \"03\"  3
+value   \"1,5 g\"  1.5 g
+result  OK
+"
+        );
+    }
+}
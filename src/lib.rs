@@ -1,10 +1,43 @@
 //! A rust library to communicate with the
 //! SOEHNLE Terminal 3005 (via RS232).
 
-use std::str::FromStr;
+use std::{
+    cmp::Ordering,
+    io,
+    num::{ParseFloatError, ParseIntError},
+    str::FromStr,
+};
 
 use thiserror::Error;
 
+mod clock;
+pub mod debug;
+#[cfg(any(test, feature = "metrics"))]
+pub mod metrics;
+#[cfg(any(test, feature = "mock"))]
+mod mock;
+mod profile;
+mod protocol;
+mod reader;
+mod session;
+mod stability;
+mod terminal;
+mod weight;
+
+pub use clock::{Clock, MockClock, SystemClock};
+#[cfg(any(test, feature = "mock"))]
+pub use mock::MockTerminal;
+pub use profile::DeviceProfile;
+pub use protocol::{
+    parse_value, FieldLayout, FieldOrder, Protocol, SignFormat, Standstill, UnitField,
+};
+pub use reader::{ChangeFilter, MultiBalanceReader};
+pub use session::{Event, Pending, Session};
+pub use stability::StabilityDetector;
+pub use terminal::{send, LinkState, Request, RetryPolicy, Terminal, TerminalConfig};
+pub use weight::{preview_net, Unit, Weight};
+
+const ENQ: u8 = 0x05;
 const ACK: u8 = 0x06;
 const NAK: u8 = 0x15;
 
@@ -14,25 +47,83 @@ const NAK: u8 = 0x15;
 pub struct Message {
     pub status: Status,
     pub id: u8,
+    pub weight_type: WeightType,
     pub value: f32,
+    /// The unit as transmitted (kilograms if the frame has none).
+    pub unit: Unit,
+    /// The device address, if the frames have an address field
+    /// (see [`Protocol::address_field_len`]).
+    pub address: Option<u8>,
+}
+
+/// The fields of a message that could be parsed,
+/// see [`Message::from_str_lenient`].
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct PartialMessage {
+    pub status: Option<Status>,
+    pub id: Option<u8>,
+    pub value: Option<f32>,
+    pub address: Option<u8>,
+}
+
+/// The meaning of a message's value as derived from its status.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ValueState {
+    /// A valid measurement.
+    Weight(f32),
+    /// The load exceeds the weighing range.
+    Overload,
+    /// The load is below the weighing range.
+    Underload,
+    /// Over- and underload are signalled at the same time.
+    Invalid,
+}
+
+/// Kind of the transmitted weight value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WeightType {
+    /// Net weight (indicator `N`).
+    #[default]
+    Net,
+    /// Gross weight (indicator `B` for *brutto* or `G`).
+    Gross,
 }
 
 /// A Command/Query response.
 #[derive(Debug, Clone, Copy, PartialEq)]
-enum Response {
+pub enum Response {
     Ack,
     Nak,
     Message(Message),
+    /// A request echoed by the terminal (see [`Protocol::echo`]).
+    Echo(Echo),
+    /// A frame without a value (see [`Protocol::status_only_frames`]).
+    StatusOnly {
+        id: u8,
+        status: Status,
+    },
 }
 
 /// Balance status.
 // TODO: use enum
 #[allow(clippy::struct_excessive_bools)]
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
 pub struct Status {
+    /// The load is below the weighing range,
+    /// the transmitted value is not a valid measurement.
     pub under_load: bool,
+    /// The load exceeds the weighing range,
+    /// the transmitted value is not a valid measurement.
     pub over_load: bool,
+    /// The weight is stable.
     pub standstill: bool,
+    /// The terminal's *empty message* (German *Leermeldung*):
+    /// the weight lies within the empty range configured on the terminal,
+    /// i.e. the platform is regarded as unloaded.
+    ///
+    /// This flag is not related to the validity of the value; the
+    /// protocol has no separate "no measurement" state, an invalid
+    /// reading is only signalled by `under_load` and `over_load`.
     pub empty_message: bool,
 }
 
@@ -41,30 +132,130 @@ pub struct Status {
 pub enum Command {
     Tare,
     ClearTare,
-    SetTare(u32),
+    SetTare(TareValue),
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Error)]
+/// Several commands that are sent one after another.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct CommandSequence(pub Vec<Command>);
+
+/// A tare value that fits into the tare field of a command
+/// (at most [`TareValue::MAX`]).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct TareValue(u32);
+
+/// Errors that may occur while encoding, parsing or communicating.
+///
+/// Parse errors carry the offending part of the input.
+#[derive(Debug, Clone, PartialEq, Error)]
 pub enum Error {
-    #[error("Invalid tare value")]
-    TareValue,
-    #[error("Invalid message length")]
-    MessageLength,
-    #[error("Non-ASCII str")]
-    NonAsciiStr,
-    #[error("Invalid balance ID")]
-    BalanceId,
-    #[error("Invalid balance value")]
-    BalanceValue,
-    #[error("Could not parse boolen")]
-    ParseBoolean,
+    #[error("Invalid tare value: {0}")]
+    TareValue(u32),
+    #[error("Invalid tare: '{0}'")]
+    TareString(String),
+    #[error("Tare has more decimal places than the tare field: '{0}'")]
+    TarePrecision(String),
+    #[error("Negative tare: {0}")]
+    NegativeTare(Weight),
+    #[error("The terminal echoed the tare {echoed} instead of {requested}")]
+    TareMismatch { requested: u32, echoed: u32 },
+    #[error("Invalid message length: '{0}'")]
+    MessageLength(String),
+    #[error("No frame end within {0} bytes")]
+    FrameTooLong(usize),
+    #[error("Non-ASCII str: '{0}'")]
+    NonAsciiStr(String),
+    #[error("Invalid balance ID: '{0}'")]
+    BalanceId(String, #[source] ParseIntError),
+    #[error("Balance ID has more than 2 digits: '{0}'")]
+    BalanceIdTooLong(String),
+    #[error("Invalid address: '{0}'")]
+    Address(String, #[source] ParseIntError),
+    #[error("Invalid balance value: '{0}'")]
+    BalanceValue(String, #[source] ParseFloatError),
+    #[error("Empty balance value")]
+    EmptyValue,
+    #[error("The balance value is not a finite number: '{0}'")]
+    NonFiniteValue(String),
+    #[error("Implausible weight value: {0}")]
+    ValueOutOfRange(Weight),
+    #[error("Invalid weight type: '{0}'")]
+    WeightType(String),
+    #[error("Invalid unit: '{0}'")]
+    Unit(String),
+    #[error("Could not parse boolen: '{0}'")]
+    ParseBoolean(String),
+    #[error("Unknown frame: '{0}'")]
+    UnknownFrame(String),
+    #[error("The terminal rejected the request (NAK)")]
+    Nak,
+    #[error("Unexpected ACK")]
+    UnexpectedAck,
+    /// A NAK where a message was expected, unlike [`Error::Nak`]
+    /// for a request that the terminal rejected.
+    #[error("Unexpected NAK")]
+    UnexpectedNak,
+    #[error("Unexpected message")]
+    UnexpectedMessage,
+    #[error("Unexpected command echo")]
+    UnexpectedEcho,
+    #[error("The terminal doesn't support the command {0:?}")]
+    UnsupportedCommand(Command),
+    #[error("No response from the terminal")]
+    Timeout,
+    #[error("The terminal is busy")]
+    DeviceBusy,
+    #[error("I/O error: {0}")]
+    Io(io::ErrorKind),
+    /// An error together with the raw bytes that caused it.
+    #[error("{source} (raw frame: {raw:02X?})")]
+    Frame { raw: Vec<u8>, source: Box<Error> },
+}
+
+impl Error {
+    /// Returns `true` for errors that may go away when the request is repeated
+    /// (rejections, timeouts and a busy terminal), `false` for e.g. encoding or parse errors.
+    #[must_use]
+    pub const fn is_retryable(&self) -> bool {
+        matches!(self, Self::Nak | Self::Timeout | Self::DeviceBusy)
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        match err.kind() {
+            io::ErrorKind::TimedOut => Self::Timeout,
+            kind => Self::Io(kind),
+        }
+    }
 }
 
 type Result<T> = std::result::Result<T, Error>;
 
+/// Turns a parse error into the variant of [`Error`] that also
+/// carries the offending input, so that parse code can use `?`:
+/// `id.parse().context(Error::BalanceId, id)?`.
+trait Context<T, E> {
+    fn context(self, variant: fn(String, E) -> Error, input: &str) -> Result<T>;
+}
+
+impl<T, E> Context<T, E> for std::result::Result<T, E> {
+    fn context(self, variant: fn(String, E) -> Error, input: &str) -> Result<T> {
+        self.map_err(|err| variant(input.into(), err))
+    }
+}
+
 /// Command or Query with ACK
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct WithAck<T>(T);
 
+/// The request in a [`Response::Echo`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Echo {
+    Command(Command),
+    Query(Query),
+}
+
 /// Balance query command
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Query {
@@ -74,6 +265,68 @@ pub enum Query {
 
 pub trait ToAsciiString {
     fn to_ascii_string(&self) -> Result<String>;
+
+    /// Encodes the frame for a terminal with the given profile.
+    ///
+    /// By default this is the same as [`ToAsciiString::to_ascii_string`].
+    fn to_ascii_string_with(&self, profile: &DeviceProfile) -> Result<String> {
+        let _ = profile;
+        self.to_ascii_string()
+    }
+
+    /// Encodes the frame followed by the `terminator`.
+    ///
+    /// ```
+    /// # use soehnle_t3005::{Command, Terminator, ToAsciiString};
+    /// assert_eq!(Command::Tare.to_ascii_frame(Terminator::Cr).unwrap(), "<T>\r");
+    /// ```
+    fn to_ascii_frame(&self, terminator: Terminator) -> Result<String> {
+        let mut frame = self.to_ascii_string()?;
+        frame.push_str(terminator.as_str());
+        Ok(frame)
+    }
+}
+
+/// Encodes borrowed commands and queries without copying them.
+impl<T: ToAsciiString + ?Sized> ToAsciiString for &T {
+    fn to_ascii_string(&self) -> Result<String> {
+        (**self).to_ascii_string()
+    }
+
+    fn to_ascii_string_with(&self, profile: &DeviceProfile) -> Result<String> {
+        (**self).to_ascii_string_with(profile)
+    }
+}
+
+/// Bytes that terminate an outgoing frame.
+///
+/// The `<...>` frames are self-delimiting, so by default nothing is appended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Terminator {
+    #[default]
+    None,
+    Cr,
+    Lf,
+    CrLf,
+    Etx,
+}
+
+impl Terminator {
+    #[must_use]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::None => "",
+            Self::Cr => "\r",
+            Self::Lf => "\n",
+            Self::CrLf => "\r\n",
+            Self::Etx => "\x03",
+        }
+    }
+
+    #[must_use]
+    pub const fn as_bytes(self) -> &'static [u8] {
+        self.as_str().as_bytes()
+    }
 }
 
 impl Command {
@@ -81,40 +334,196 @@ impl Command {
     pub const fn with_ack(self) -> WithAck<Command> {
         WithAck(self)
     }
-}
 
-const MAX_TARE_VALUE: u32 = 9_999_999;
+    /// Creates a `SetTare` command from a decimal string like `"1.5"` or `"1,5"`.
+    ///
+    /// The value is converted into the integer tare field
+    /// according to [`DeviceProfile::tare_decimals`].
+    pub fn set_tare_str(s: &str, profile: &DeviceProfile) -> Result<Command> {
+        let s = s.trim();
+        let invalid = || Error::TareString(s.into());
+        let (int, frac) = s.split_once(['.', ',']).unwrap_or((s, ""));
+        let is_number = |digits: &str| digits.bytes().all(|b| b.is_ascii_digit());
+        if int.is_empty() || !is_number(int) || !is_number(frac) {
+            return Err(invalid());
+        }
+        let decimals = usize::try_from(profile.tare_decimals).map_err(|_| invalid())?;
+        let frac = frac.trim_end_matches('0');
+        if frac.len() > decimals {
+            return Err(Error::TarePrecision(s.into()));
+        }
+        let value = format!("{int}{frac:0<decimals$}")
+            .parse::<u64>()
+            .map_err(|_| invalid())?;
+        let value = u32::try_from(value).unwrap_or(u32::MAX);
+        validate_tare(value, profile)?;
+        Ok(Command::SetTare(TareValue::new(value)?))
+    }
 
-impl ToAsciiString for Command {
-    fn to_ascii_string(&self) -> Result<String> {
-        let string = match *self {
-            Self::Tare => "<T>".into(),
-            Self::ClearTare => "<TC>".into(),
+    /// Creates a `SetTare` command that uses the weight of `msg` as tare,
+    /// e.g. to tare the current net weight.
+    ///
+    /// The weight is converted into the [`DeviceProfile::tare_unit`] and rounded
+    /// to [`DeviceProfile::tare_decimals`]; negative weights are rejected
+    /// with [`Error::NegativeTare`].
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    pub fn tare_from_message(msg: &Message, profile: &DeviceProfile) -> Result<Command> {
+        let tare = msg.weight().to_unit(profile.tare_unit);
+        if !tare.value.is_finite() {
+            return Err(Error::NonFiniteValue(tare.value.to_string()));
+        }
+        if tare.value < 0.0 {
+            return Err(Error::NegativeTare(tare));
+        }
+        let scale = i32::try_from(profile.tare_decimals).map_or(f64::INFINITY, |d| 10_f64.powi(d));
+        let scaled = (f64::from(tare.value) * scale).round();
+        // Saturates, like a too long tare string
+        let value = scaled as u32;
+        validate_tare(value, profile)?;
+        Ok(Command::SetTare(TareValue::new(value)?))
+    }
+
+    /// The number of bytes of the encoded frame (without terminator).
+    pub fn encoded_len(&self) -> Result<usize> {
+        self.encoded_len_with(&DeviceProfile::default())
+    }
+
+    /// Like [`Command::encoded_len`] for a terminal with the given profile.
+    ///
+    /// This is the same for the command with and without ACK.
+    pub fn encoded_len_with(&self, profile: &DeviceProfile) -> Result<usize> {
+        if !profile.supports(self) {
+            return Err(Error::UnsupportedCommand(*self));
+        }
+        match self {
+            Self::Tare => Ok(3),
+            Self::ClearTare => Ok(4),
             Self::SetTare(val) => {
-                if val > MAX_TARE_VALUE {
-                    return Err(Error::TareValue);
-                }
-                format!("<T{val:07}>")
+                validate_tare(val.get(), profile)?;
+                Ok(3 + profile.tare_digits)
+            }
+        }
+    }
+
+    fn encode(self, ack: bool, profile: &DeviceProfile) -> Result<String> {
+        if !profile.supports(&self) {
+            return Err(Error::UnsupportedCommand(self));
+        }
+        let t = if ack { 't' } else { 'T' };
+        let string = match self {
+            Self::Tare => format!("<{t}>"),
+            Self::ClearTare => format!("<{t}C>"),
+            Self::SetTare(val) => {
+                let val = val.get();
+                validate_tare(val, profile)?;
+                format!("<{t}{val:0width$}>", width = profile.tare_digits)
             }
         };
         Ok(string)
     }
 }
 
-//TODO: merge with "Command" impl
+const MAX_TARE_VALUE: u32 = 9_999_999;
+
+/// Checks that a value fits into the tare field of a `SetTare` command,
+/// e.g. to validate a form field before building the command.
+///
+/// The error is the same as the one of encoding the command:
+///
+/// ```
+/// # use soehnle_t3005::{validate_tare, DeviceProfile, Error};
+/// let profile = DeviceProfile { tare_digits: 4, ..DeviceProfile::default() };
+/// assert_eq!(validate_tare(9999, &profile), Ok(()));
+/// assert_eq!(validate_tare(10_000, &profile), Err(Error::TareValue(10_000)));
+/// ```
+pub fn validate_tare(value: u32, profile: &DeviceProfile) -> Result<()> {
+    if value > MAX_TARE_VALUE.min(profile.max_tare_value()) {
+        return Err(Error::TareValue(value));
+    }
+    Ok(())
+}
+
+impl TareValue {
+    /// The largest value the 7 digit tare field can hold.
+    pub const MAX: Self = Self(MAX_TARE_VALUE);
+
+    pub const fn new(value: u32) -> Result<Self> {
+        if value > MAX_TARE_VALUE {
+            return Err(Error::TareValue(value));
+        }
+        Ok(Self(value))
+    }
+
+    #[must_use]
+    pub const fn get(self) -> u32 {
+        self.0
+    }
+}
+
+impl TryFrom<u32> for TareValue {
+    type Error = Error;
+    fn try_from(value: u32) -> Result<Self> {
+        Self::new(value)
+    }
+}
+
+impl From<u16> for TareValue {
+    fn from(value: u16) -> Self {
+        Self(value.into())
+    }
+}
+
+impl From<TareValue> for u32 {
+    fn from(value: TareValue) -> Self {
+        value.0
+    }
+}
+
+impl ToAsciiString for Command {
+    fn to_ascii_string(&self) -> Result<String> {
+        self.to_ascii_string_with(&DeviceProfile::default())
+    }
+
+    fn to_ascii_string_with(&self, profile: &DeviceProfile) -> Result<String> {
+        self.encode(false, profile)
+    }
+}
+
 impl ToAsciiString for WithAck<Command> {
     fn to_ascii_string(&self) -> Result<String> {
-        let string = match self.0 {
-            Command::Tare => "<t>".into(),
-            Command::ClearTare => "<tC>".into(),
-            Command::SetTare(val) => {
-                if val > MAX_TARE_VALUE {
-                    return Err(Error::TareValue);
-                }
-                format!("<t{val:07}>")
-            }
-        };
-        Ok(string)
+        self.to_ascii_string_with(&DeviceProfile::default())
+    }
+
+    fn to_ascii_string_with(&self, profile: &DeviceProfile) -> Result<String> {
+        self.0.encode(true, profile)
+    }
+}
+
+impl From<Vec<Command>> for CommandSequence {
+    fn from(commands: Vec<Command>) -> Self {
+        Self(commands)
+    }
+}
+
+impl FromIterator<Command> for CommandSequence {
+    fn from_iter<I: IntoIterator<Item = Command>>(iter: I) -> Self {
+        Self(iter.into_iter().collect())
+    }
+}
+
+/// Concatenates the frames of all commands.
+///
+/// Encoding fails if any of the commands can't be encoded.
+impl ToAsciiString for CommandSequence {
+    fn to_ascii_string(&self) -> Result<String> {
+        self.to_ascii_string_with(&DeviceProfile::default())
+    }
+
+    fn to_ascii_string_with(&self, profile: &DeviceProfile) -> Result<String> {
+        self.0
+            .iter()
+            .map(|cmd| cmd.to_ascii_string_with(profile))
+            .collect()
     }
 }
 
@@ -145,47 +554,404 @@ impl ToAsciiString for WithAck<Query> {
     }
 }
 
+impl Message {
+    /// Creates a message with a normal status (no flags set).
+    ///
+    /// Use the `with_*` methods to set status flags:
+    ///
+    /// ```
+    /// # use soehnle_t3005::Message;
+    /// let msg = Message::new(3, 1.5).with_standstill();
+    /// assert!(msg.status.standstill);
+    /// assert!(!msg.status.over_load);
+    /// ```
+    #[must_use]
+    pub const fn new(id: u8, value: f32) -> Self {
+        Self {
+            status: Status {
+                under_load: false,
+                over_load: false,
+                standstill: false,
+                empty_message: false,
+            },
+            id,
+            weight_type: WeightType::Net,
+            value,
+            unit: Unit::Kilogram,
+            address: None,
+        }
+    }
+
+    #[must_use]
+    pub const fn with_unit(mut self, unit: Unit) -> Self {
+        self.unit = unit;
+        self
+    }
+
+    #[must_use]
+    pub const fn with_address(mut self, address: u8) -> Self {
+        self.address = Some(address);
+        self
+    }
+
+    /// The value together with its unit.
+    #[must_use]
+    pub const fn weight(&self) -> Weight {
+        Weight::new(self.value, self.unit)
+    }
+
+    /// Subtracts a tare that is managed by the host from the value,
+    /// which is taken as the gross weight (see [`Message::weight_type`]).
+    ///
+    /// The result has the unit of the message:
+    ///
+    /// ```
+    /// # use soehnle_t3005::{Message, Unit, Weight, WeightType};
+    /// let msg = Message::new(1, 1200.0)
+    ///     .with_unit(Unit::Gram)
+    ///     .with_weight_type(WeightType::Gross);
+    /// assert_eq!(msg.net_with_tare(Weight::kilograms(0.2)), Weight::grams(1000.0));
+    /// ```
+    #[must_use]
+    pub fn net_with_tare(&self, tare: Weight) -> Weight {
+        preview_net(self.weight(), tare)
+    }
+
+    #[must_use]
+    pub const fn with_weight_type(mut self, weight_type: WeightType) -> Self {
+        self.weight_type = weight_type;
+        self
+    }
+
+    #[must_use]
+    pub const fn with_status(mut self, status: Status) -> Self {
+        self.status = status;
+        self
+    }
+
+    #[must_use]
+    pub const fn with_under_load(mut self) -> Self {
+        self.status.under_load = true;
+        self
+    }
+
+    #[must_use]
+    pub const fn with_over_load(mut self) -> Self {
+        self.status.over_load = true;
+        self
+    }
+
+    #[must_use]
+    pub const fn with_standstill(mut self) -> Self {
+        self.status.standstill = true;
+        self
+    }
+
+    #[must_use]
+    pub const fn with_empty_message(mut self) -> Self {
+        self.status.empty_message = true;
+        self
+    }
+
+    /// Compares two messages by their weight only, in kilograms,
+    /// so that e.g. `900 g` is lighter than `1,5 kg`.
+    ///
+    /// The derived `PartialEq` also looks at the status and the balance ID,
+    /// so use this e.g. to find the heaviest reading of a batch:
+    ///
+    /// ```
+    /// # use std::str::FromStr;
+    /// # use soehnle_t3005::Message;
+    /// let messages = [
+    ///     Message::from_str("000101N        3,1 kg").unwrap(),
+    ///     Message::from_str("001001N        7,5 kg").unwrap(),
+    ///     Message::from_str("000101N       -3,9 kg").unwrap(),
+    /// ];
+    /// let heaviest = messages.iter().max_by(|a, b| a.cmp_by_value(b)).unwrap();
+    /// assert_eq!(heaviest.value, 7.5);
+    /// ```
+    ///
+    /// The comparison is total (see [`f32::total_cmp`]).
+    #[must_use]
+    pub fn cmp_by_value(&self, other: &Self) -> Ordering {
+        let kg = |msg: &Self| msg.weight().to_kilograms();
+        kg(self).total_cmp(&kg(other))
+    }
+
+    /// Whether both messages report the same reading of the same balance.
+    ///
+    /// Unlike the derived `PartialEq` the status and the weight type are
+    /// ignored, so a toggling standstill flag does not count as a change.
+    #[must_use]
+    pub fn same_reading(&self, other: &Self) -> bool {
+        self.id == other.id
+            && self.unit == other.unit
+            && (self.value - other.value).abs() <= f32::EPSILON
+    }
+
+    /// The value, if the status indicates a valid measurement.
+    ///
+    /// ```
+    /// # use soehnle_t3005::{Message, ValueState};
+    /// match Message::new(1, 2.5).value_state() {
+    ///     ValueState::Weight(kg) => assert_eq!(kg, 2.5),
+    ///     _ => unreachable!(),
+    /// }
+    /// ```
+    #[must_use]
+    pub const fn value_state(&self) -> ValueState {
+        match (self.status.over_load, self.status.under_load) {
+            (false, false) => ValueState::Weight(self.value),
+            (true, false) => ValueState::Overload,
+            (false, true) => ValueState::Underload,
+            (true, true) => ValueState::Invalid,
+        }
+    }
+
+    /// Parses all CR/LF terminated frames contained in `buf`.
+    ///
+    /// Each frame is parsed on its own, so a malformed frame
+    /// does not prevent the following ones from being parsed.
+    /// Empty lines are skipped.
+    #[must_use]
+    pub fn parse_all(buf: &str) -> Vec<Result<Self>> {
+        buf.split(['\r', '\n'])
+            .filter(|frame| !frame.trim().is_empty())
+            .map(Self::from_str)
+            .collect()
+    }
+
+    /// Formats the message as a CSV row with the columns
+    /// `id,value,stable,overload,underload,empty`.
+    ///
+    /// The value is given in the transmitted unit, the flags as `1` or `0`:
+    ///
+    /// ```
+    /// # use soehnle_t3005::Message;
+    /// let msg = Message::new(3, -1.5).with_standstill();
+    /// assert_eq!(msg.to_csv_row(), "3,-1.5,1,0,0,0");
+    /// ```
+    #[must_use]
+    pub fn to_csv_row(&self) -> String {
+        let Status {
+            under_load,
+            over_load,
+            standstill,
+            empty_message,
+        } = self.status;
+        format!(
+            "{},{},{},{},{},{}",
+            self.id,
+            self.value,
+            u8::from(standstill),
+            u8::from(over_load),
+            u8::from(under_load),
+            u8::from(empty_message)
+        )
+    }
+
+    /// Formats the message as a compact JSON object.
+    ///
+    /// ```
+    /// # use soehnle_t3005::Message;
+    /// let msg = Message::new(3, -1.5).with_standstill();
+    /// assert_eq!(
+    ///     msg.to_json_string(),
+    ///     r#"{"id":3,"value":-1.5,"unit":"kg","stable":true,"overload":false,"underload":false,"empty":false}"#
+    /// );
+    /// ```
+    #[must_use]
+    pub fn to_json_string(&self) -> String {
+        let value = if self.value.is_finite() {
+            self.value.to_string()
+        } else {
+            "null".into()
+        };
+        format!(
+            r#"{{"id":{},"value":{value},"unit":"{}","stable":{},"overload":{},"underload":{},"empty":{}}}"#,
+            self.id,
+            self.unit,
+            self.status.standstill,
+            self.status.over_load,
+            self.status.under_load,
+            self.status.empty_message
+        )
+    }
+
+    /// Like [`Message::from_str`] but keeps the fields that could be parsed
+    /// if others are invalid.
+    ///
+    /// ```
+    /// # use soehnle_t3005::Message;
+    /// let partial = Message::from_str_lenient("010003N       3,x kg");
+    /// assert_eq!(partial.id, Some(3));
+    /// assert!(partial.status.unwrap().over_load);
+    /// assert_eq!(partial.value, None);
+    /// ```
+    #[must_use]
+    pub fn from_str_lenient(s: &str) -> PartialMessage {
+        Protocol::default().parse_partial(s)
+    }
+}
+
 impl FromStr for Message {
     type Err = Error;
     fn from_str(s: &str) -> Result<Self> {
-        let s = s.trim();
-        if (s.len() > 27) || (s.len() < 7) {
-            return Err(Error::MessageLength);
+        Protocol::default().parse_message(s)
+    }
+}
+
+/// Splits a `<...>` frame into its (uppercased) command letter,
+/// the arguments and the ACK flag (a lowercase command letter).
+fn parse_frame(s: &str) -> Result<(char, &str, bool)> {
+    let frame = s.trim();
+    let unknown = || Error::UnknownFrame(frame.into());
+    let payload = frame
+        .strip_prefix('<')
+        .and_then(|f| f.strip_suffix('>'))
+        .ok_or_else(unknown)?;
+    let letter = payload.chars().next().ok_or_else(unknown)?;
+    let args = &payload[letter.len_utf8()..];
+    Ok((
+        letter.to_ascii_uppercase(),
+        args,
+        letter.is_ascii_lowercase(),
+    ))
+}
+
+fn parse_command(s: &str) -> Result<(Command, bool)> {
+    let (letter, args, ack) = parse_frame(s)?;
+    let unknown = || Error::UnknownFrame(s.trim().into());
+    let cmd = match (letter, args) {
+        ('T', "") => Command::Tare,
+        ('T', "C") => Command::ClearTare,
+        ('T', digits) if digits.bytes().all(|b| b.is_ascii_digit()) => {
+            let val = digits.parse().map_err(|_| unknown())?;
+            Command::SetTare(TareValue::new(val)?)
         }
-        if !s.is_ascii() {
-            return Err(Error::NonAsciiStr);
+        _ => return Err(unknown()),
+    };
+    Ok((cmd, ack))
+}
+
+fn parse_query(s: &str) -> Result<(Query, bool)> {
+    let (letter, args, ack) = parse_frame(s)?;
+    let query = match (letter, args) {
+        ('A', "") => Query::Once,
+        ('B', "") => Query::OnceOnChange,
+        _ => return Err(Error::UnknownFrame(s.trim().into())),
+    };
+    Ok((query, ack))
+}
+
+impl FromStr for Command {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self> {
+        match parse_command(s)? {
+            (cmd, false) => Ok(cmd),
+            (_, true) => Err(Error::UnknownFrame(s.trim().into())),
         }
-        let (status, tail) = s.split_at(4);
-        let (id, netto) = tail.split_at(2);
-        let v = netto
-            .replace('N', "")
-            .replace("kg", "")
-            .replace(' ', "")
-            .replace(',', ".");
-
-        Ok(Message {
-            status: Status::from_str(status)?,
-            id: id.replace('W', "").parse().map_err(|_| Error::BalanceId)?,
-            value: v.trim().parse().map_err(|_| Error::BalanceValue)?,
-        })
     }
 }
 
-impl FromStr for Response {
+impl FromStr for WithAck<Command> {
     type Err = Error;
     fn from_str(s: &str) -> Result<Self> {
-        let s = s.trim();
-        if s.is_empty() {
-            return Err(Error::MessageLength);
+        match parse_command(s)? {
+            (cmd, true) => Ok(cmd.with_ack()),
+            (_, false) => Err(Error::UnknownFrame(s.trim().into())),
         }
-        if s.as_bytes()[0] == ACK {
-            return Ok(Response::Ack);
+    }
+}
+
+impl FromStr for Query {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self> {
+        match parse_query(s)? {
+            (query, false) => Ok(query),
+            (_, true) => Err(Error::UnknownFrame(s.trim().into())),
         }
-        if s.as_bytes()[0] == NAK {
-            return Ok(Response::Nak);
+    }
+}
+
+impl FromStr for WithAck<Query> {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self> {
+        match parse_query(s)? {
+            (query, true) => Ok(query.with_ack()),
+            (_, false) => Err(Error::UnknownFrame(s.trim().into())),
+        }
+    }
+}
+
+impl Response {
+    /// Like [`Response::from_str`] but reports a NAK as [`Error::Nak`],
+    /// so that rejections can be propagated with `?`.
+    pub fn from_str_strict(s: &str) -> Result<Self> {
+        match Self::from_str(s)? {
+            Self::Nak => Err(Error::Nak),
+            response => Ok(response),
+        }
+    }
+}
+
+/// The value in kilograms, regardless of the status.
+///
+/// Check [`Message::value_state`] first if the validity matters.
+impl From<Message> for f32 {
+    fn from(msg: Message) -> Self {
+        msg.weight().to_kilograms()
+    }
+}
+
+/// The weight, regardless of the status.
+///
+/// Check [`Message::value_state`] first if the validity matters.
+impl From<Message> for Weight {
+    fn from(msg: Message) -> Self {
+        msg.weight()
+    }
+}
+
+/// Extracts the message of a response.
+///
+/// An ACK or NAK is reported as [`Error::UnexpectedAck`] or [`Error::UnexpectedNak`];
+/// a [`Terminal`] reports a NAK to a query as [`Error::Nak`] instead.
+///
+/// ```
+/// # use soehnle_t3005::{Error, Message, Response};
+/// let msg = Message::try_from(Response::Message(Message::new(1, 2.5))).unwrap();
+/// assert_eq!(msg.value, 2.5);
+/// assert_eq!(Message::try_from(Response::Ack), Err(Error::UnexpectedAck));
+/// ```
+impl TryFrom<Response> for Message {
+    type Error = Error;
+    fn try_from(response: Response) -> Result<Self> {
+        match response {
+            Response::Message(msg) => Ok(msg),
+            Response::Nak => Err(Error::UnexpectedNak),
+            Response::Ack => Err(Error::UnexpectedAck),
+            Response::Echo(_) => Err(Error::UnexpectedEcho),
+            Response::StatusOnly { .. } => Err(Error::EmptyValue),
+        }
+    }
+}
+
+impl FromStr for Response {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self> {
+        Protocol::default().parse_response(s)
+    }
+}
+
+impl FromStr for WeightType {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "N" => Ok(Self::Net),
+            "B" | "G" => Ok(Self::Gross),
+            _ => Err(Error::WeightType(s.into())),
         }
-        let msg = Message::from_str(s)?;
-        Ok(Response::Message(msg))
     }
 }
 
@@ -193,10 +959,10 @@ impl FromStr for Status {
     type Err = Error;
     fn from_str(s: &str) -> Result<Self> {
         if s.len() != 4 {
-            return Err(Error::MessageLength);
+            return Err(Error::MessageLength(s.into()));
         }
         if !s.is_ascii() {
-            return Err(Error::NonAsciiStr);
+            return Err(Error::NonAsciiStr(s.into()));
         }
         let (under_load, tail) = s.split_at(1);
         let (over_load, tail) = tail.split_at(1);
@@ -212,15 +978,49 @@ impl FromStr for Status {
     }
 }
 
+impl Status {
+    /// Reconstructs a status from the compact form produced by
+    /// `u8::from(status)`.
+    ///
+    /// | bit | flag            |
+    /// |-----|-----------------|
+    /// | 0   | `under_load`    |
+    /// | 1   | `over_load`     |
+    /// | 2   | `standstill`    |
+    /// | 3   | `empty_message` |
+    ///
+    /// The bits above are ignored.
+    #[must_use]
+    pub const fn from_bits(bits: u8) -> Self {
+        Self {
+            under_load: bits & 0b0001 != 0,
+            over_load: bits & 0b0010 != 0,
+            standstill: bits & 0b0100 != 0,
+            empty_message: bits & 0b1000 != 0,
+        }
+    }
+}
+
+impl From<Status> for u8 {
+    /// Packs the flags into the lower four bits, see [`Status::from_bits`].
+    fn from(status: Status) -> Self {
+        u8::from(status.under_load)
+            | u8::from(status.over_load) << 1
+            | u8::from(status.standstill) << 2
+            | u8::from(status.empty_message) << 3
+    }
+}
+
 fn bool_from_str(s: &str) -> Result<bool> {
     match s {
         "1" => Ok(true),
         "0" => Ok(false),
-        _ => Err(Error::ParseBoolean),
+        _ => Err(Error::ParseBoolean(s.into())),
     }
 }
 
 #[cfg(test)]
+#[allow(clippy::float_cmp)]
 mod tests {
 
     use super::*;
@@ -249,6 +1049,167 @@ mod tests {
         );
     }
 
+    #[test]
+    fn message_to_csv_and_json() {
+        let msg = Message::from_str("110107N     1234,5 g").unwrap();
+        assert_eq!(msg.to_csv_row(), "7,1234.5,0,1,1,1");
+        assert_eq!(
+            msg.to_json_string(),
+            r#"{"id":7,"value":1234.5,"unit":"g","stable":false,"overload":true,"underload":true,"empty":true}"#
+        );
+        let msg = Message::new(1, 0.0);
+        assert_eq!(msg.to_csv_row(), "1,0,0,0,0,0");
+        assert_eq!(
+            Message::new(1, f32::NAN).to_json_string(),
+            r#"{"id":1,"value":null,"unit":"kg","stable":false,"overload":false,"underload":false,"empty":false}"#
+        );
+    }
+
+    #[test]
+    fn compare_readings_without_status() {
+        let msg = Message::new(1, 3.1);
+        let stable = msg.with_standstill();
+        assert_ne!(msg, stable);
+        assert!(msg.same_reading(&stable));
+        assert!(msg.same_reading(&msg.with_weight_type(WeightType::Gross)));
+        assert!(msg.same_reading(&Message::new(1, 3.1 + f32::EPSILON / 2.0)));
+
+        assert!(!msg.same_reading(&Message::new(2, 3.1)));
+        assert!(!msg.same_reading(&Message::new(1, 3.2)));
+        assert!(!msg.same_reading(&msg.with_unit(Unit::Gram)));
+    }
+
+    #[test]
+    fn value_state_from_status() {
+        let msg = Message::new(1, 3.5);
+        assert_eq!(msg.value_state(), ValueState::Weight(3.5));
+        assert_eq!(
+            msg.with_standstill().with_empty_message().value_state(),
+            ValueState::Weight(3.5)
+        );
+        assert_eq!(msg.with_over_load().value_state(), ValueState::Overload);
+        assert_eq!(msg.with_under_load().value_state(), ValueState::Underload);
+        assert_eq!(
+            msg.with_over_load().with_under_load().value_state(),
+            ValueState::Invalid
+        );
+        assert_eq!(
+            Message::from_str("010001N     9999,9 kg")
+                .unwrap()
+                .value_state(),
+            ValueState::Overload
+        );
+    }
+
+    #[test]
+    fn build_message() {
+        let msg = Message::new(3, 1.5);
+        assert_eq!(msg.id, 3);
+        assert_eq!(msg.value, 1.5);
+        assert_eq!(msg.status, Status::default());
+
+        assert!(Message::new(0, 0.0).with_under_load().status.under_load);
+        assert!(Message::new(0, 0.0).with_over_load().status.over_load);
+        assert!(Message::new(0, 0.0).with_standstill().status.standstill);
+        assert_eq!(Message::new(0, 0.0).with_address(7).address, Some(7));
+        assert!(
+            Message::new(0, 0.0)
+                .with_empty_message()
+                .status
+                .empty_message
+        );
+        assert_eq!(
+            Message::from_str("101103N        1,5 kg").unwrap(),
+            Message::new(3, 1.5)
+                .with_under_load()
+                .with_standstill()
+                .with_empty_message()
+        );
+        let status = Status::from_str("0100").unwrap();
+        assert_eq!(Message::new(1, 2.0).with_status(status).status, status);
+    }
+
+    #[test]
+    fn compare_messages_by_value() {
+        let light = Message::from_str("001101N        3,1 kg").unwrap();
+        let heavy = Message::from_str("000001N        7,5 kg").unwrap();
+        let negative = Message::from_str("000001N       -3,9 kg").unwrap();
+        assert_eq!(light.cmp_by_value(&heavy), Ordering::Less);
+        assert_eq!(heavy.cmp_by_value(&light), Ordering::Greater);
+        assert_eq!(negative.cmp_by_value(&light), Ordering::Less);
+        assert_eq!(light.cmp_by_value(&light), Ordering::Equal);
+
+        let grams = Message::new(1, 900.0).with_unit(Unit::Gram);
+        let kilograms = Message::new(1, 1.5);
+        assert_eq!(grams.cmp_by_value(&kilograms), Ordering::Less);
+        assert_eq!(
+            Message::new(1, 1500.0)
+                .with_unit(Unit::Gram)
+                .cmp_by_value(&kilograms),
+            Ordering::Equal
+        );
+
+        let messages = [light, heavy, negative];
+        let max = messages.iter().max_by(|a, b| a.cmp_by_value(b)).unwrap();
+        assert_eq!(*max, heavy);
+        let min = messages.iter().min_by(|a, b| a.cmp_by_value(b)).unwrap();
+        assert_eq!(*min, negative);
+    }
+
+    #[test]
+    fn parse_weight_type_from_message() {
+        let net = Message::from_str("000101N        3,1 kg").unwrap();
+        assert_eq!(net.weight_type, WeightType::Net);
+        assert_eq!(net.value, 3.1);
+
+        let gross = Message::from_str("000101B        3,1 kg").unwrap();
+        assert_eq!(gross.weight_type, WeightType::Gross);
+        assert_eq!(gross.value, 3.1);
+
+        let gross = Message::from_str("000101G       -3,9 kg").unwrap();
+        assert_eq!(gross.weight_type, WeightType::Gross);
+        assert_eq!(gross.value, -3.9);
+
+        assert_eq!(
+            Message::from_str("000101X        3,1 kg"),
+            Err(Error::WeightType("X".into()))
+        );
+        assert_eq!(
+            Message::from_str("000101         3,1 kg"),
+            Err(Error::WeightType(" ".into()))
+        );
+        assert_eq!(
+            Message::new(1, 3.1).with_weight_type(WeightType::Gross),
+            Message::from_str("000001B        3,1 kg").unwrap()
+        );
+    }
+
+    #[test]
+    fn parse_unit_from_message() {
+        let msg = Message::from_str("000101N        3,1 kg").unwrap();
+        assert_eq!(msg.unit, Unit::Kilogram);
+        assert_eq!(msg.weight(), Weight::kilograms(3.1));
+
+        let msg = Message::from_str("000101N       3100 g").unwrap();
+        assert_eq!(msg.unit, Unit::Gram);
+        assert_eq!(msg.value, 3100.0);
+
+        let msg = Message::from_str("000101N      -10,0 lb").unwrap();
+        assert_eq!(msg.unit, Unit::Pound);
+        assert_eq!(msg.value, -10.0);
+        assert_eq!(msg.weight().to_kilograms(), -4.535_923_7);
+
+        let msg = Message::from_str("000101N        3,1").unwrap();
+        assert_eq!(msg.unit, Unit::Kilogram);
+        assert_eq!(msg.value, 3.1);
+
+        assert!(Message::from_str("000101N        3,1 oz").is_err());
+        assert_eq!(
+            Message::new(1, 2.0).with_unit(Unit::Pound).weight(),
+            Weight::pounds(2.0)
+        );
+    }
+
     #[test]
     fn parse_id_from_message() {
         assert!(Message::from_str("0000XXN    -1000,0 kg").is_err());
@@ -268,6 +1229,92 @@ mod tests {
         assert!(Message::from_str("000�ۿ3,9 kg").is_err());
     }
 
+    #[test]
+    fn parse_message_with_empty_value() {
+        assert_eq!(
+            Message::from_str("000101N           kg"),
+            Err(Error::EmptyValue)
+        );
+        assert_eq!(
+            Message::from_str("000101N              "),
+            Err(Error::EmptyValue)
+        );
+        assert_eq!(
+            Message::from_str("000101N       3,x kg"),
+            Err(Error::BalanceValue(
+                "N       3,x kg".into(),
+                "3.x".parse::<f32>().unwrap_err()
+            ))
+        );
+    }
+
+    #[test]
+    fn parse_all_messages_from_buffer() {
+        let buf = "000101N        3,1 kg\r\n0000XXN    -1000,0 kg\r\n000003N       -3,9 kg\r\n";
+        let results = Message::parse_all(buf);
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[0].as_ref().unwrap().value, 3.1);
+        assert!(matches!(&results[1], Err(Error::BalanceId(id, _)) if id == "XX"));
+        assert_eq!(results[2].as_ref().unwrap().id, 3);
+        assert_eq!(results[2].as_ref().unwrap().value, -3.9);
+
+        assert!(Message::parse_all("").is_empty());
+        assert_eq!(Message::parse_all("000101N        3,1 kg").len(), 1);
+    }
+
+    #[test]
+    fn error_display_contains_input() {
+        assert_eq!(
+            Message::from_str("000101N       3,x kg")
+                .unwrap_err()
+                .to_string(),
+            "Invalid balance value: 'N       3,x kg'"
+        );
+        assert_eq!(
+            Message::from_str("0000XXN    -1000,0 kg")
+                .unwrap_err()
+                .to_string(),
+            "Invalid balance ID: 'XX'"
+        );
+        assert_eq!(
+            Message::from_str("0X0001N    -1000,0 kg")
+                .unwrap_err()
+                .to_string(),
+            "Could not parse boolen: 'X'"
+        );
+        assert_eq!(
+            TareValue::new(10_000_000).unwrap_err().to_string(),
+            "Invalid tare value: 10000000"
+        );
+    }
+
+    #[test]
+    fn parse_error_source() {
+        use std::error::Error as _;
+
+        let err = Message::from_str("0000XXN    -1000,0 kg").unwrap_err();
+        assert!(matches!(err, Error::BalanceId(ref id, _) if id == "XX"));
+        let source = err.source().unwrap();
+        assert!(source.downcast_ref::<ParseIntError>().is_some());
+
+        let err = Message::from_str("000000N       3,x kg").unwrap_err();
+        assert!(matches!(err, Error::BalanceValue(..)));
+        let source = err.source().unwrap();
+        assert_eq!(
+            source.downcast_ref::<ParseFloatError>(),
+            Some(&"3.x".parse::<f32>().unwrap_err())
+        );
+
+        assert!(Error::EmptyValue.source().is_none());
+
+        let err = "1x"
+            .parse::<u8>()
+            .context(Error::Address, "1x")
+            .unwrap_err();
+        assert!(matches!(err, Error::Address(ref address, _) if address == "1x"));
+        assert!(err.source().unwrap().is::<ParseIntError>());
+    }
+
     #[test]
     fn parse_response() {
         assert!(Response::from_str("").is_err());
@@ -281,16 +1328,70 @@ mod tests {
         );
         assert_eq!(
             Response::from_str("0000W9N    -1000,0 kg").unwrap(),
-            Response::Message(Message {
-                id: 9,
-                status: Status {
-                    empty_message: false,
-                    over_load: false,
-                    under_load: false,
-                    standstill: false,
-                },
-                value: -1000.0
-            })
+            Response::Message(Message::new(9, -1000.0))
+        );
+    }
+
+    #[test]
+    fn parse_response_strict() {
+        let ack = ::std::str::from_utf8(&[ACK]).unwrap();
+        let nak = ::std::str::from_utf8(&[NAK]).unwrap();
+        assert_eq!(Response::from_str(nak).unwrap(), Response::Nak);
+        assert_eq!(Response::from_str_strict(nak), Err(Error::Nak));
+        assert_eq!(Response::from_str_strict(ack).unwrap(), Response::Ack);
+        assert_eq!(
+            Response::from_str_strict("0000W9N    -1000,0 kg").unwrap(),
+            Response::Message(Message::new(9, -1000.0))
+        );
+        assert!(Response::from_str_strict("").is_err());
+    }
+
+    #[test]
+    fn net_with_host_tare() {
+        let msg = Message::new(1, 12.5).with_weight_type(WeightType::Gross);
+        assert_eq!(
+            msg.net_with_tare(Weight::grams(2500.0)),
+            Weight::kilograms(10.0)
+        );
+        assert_eq!(msg.net_with_tare(Weight::zero()), Weight::kilograms(12.5));
+        assert_eq!(
+            msg.net_with_tare(Weight::kilograms(15.0)),
+            Weight::kilograms(-2.5)
+        );
+        let msg = Message::new(1, -100.0).with_unit(Unit::Gram);
+        assert_eq!(
+            msg.net_with_tare(Weight::kilograms(0.5)),
+            Weight::grams(-600.0)
+        );
+    }
+
+    #[test]
+    fn message_into_value() {
+        let kg: f32 = Message::new(1, 2.5).into();
+        assert_eq!(kg, 2.5);
+        let kg: f32 = Message::new(1, 250.0).with_unit(Unit::Gram).into();
+        assert_eq!(kg, 0.25);
+        let kg: f32 = Message::new(1, 9999.9).with_over_load().into();
+        assert_eq!(kg, 9999.9);
+
+        let msg = Message::new(1, 250.0).with_unit(Unit::Gram);
+        assert_eq!(Weight::from(msg), Weight::grams(250.0));
+    }
+
+    #[test]
+    fn message_from_response() {
+        let msg = Message::new(9, -1000.0);
+        assert_eq!(Message::try_from(Response::Message(msg)), Ok(msg));
+        assert_eq!(Message::try_from(Response::Ack), Err(Error::UnexpectedAck));
+        assert_eq!(Message::try_from(Response::Nak), Err(Error::UnexpectedNak));
+        assert_eq!(
+            Message::try_from(Response::Echo(Echo::Command(Command::Tare))),
+            Err(Error::UnexpectedEcho)
+        );
+        let status = Status::default();
+        assert_eq!(
+            Message::try_from(Response::StatusOnly { id: 1, status }),
+            Err(Error::EmptyValue)
         );
     }
 
@@ -338,6 +1439,20 @@ mod tests {
         assert!(s.empty_message);
     }
 
+    #[test]
+    fn status_bits_round_trip() {
+        for bits in 0..16 {
+            let s = Status::from_bits(bits);
+            assert_eq!(u8::from(s), bits);
+            assert_eq!(Status::from_bits(u8::from(s)), s);
+        }
+        assert_eq!(u8::from(Status::from_str("0010").unwrap()), 0b0100);
+        assert_eq!(
+            Status::from_bits(0b1111_0001),
+            Status::from_str("1000").unwrap()
+        );
+    }
+
     #[test]
     fn parse_status_from_bad_str() {
         assert!(Status::from_str("").is_err());
@@ -346,50 +1461,276 @@ mod tests {
     }
 
     #[test]
-    fn command_to_ascii_string() {
-        assert_eq!(Command::Tare.to_ascii_string().unwrap(), "<T>");
-        assert_eq!(Command::ClearTare.to_ascii_string().unwrap(), "<TC>");
-        assert_eq!(Command::SetTare(0).to_ascii_string().unwrap(), "<T0000000>");
+    fn tare_value() {
+        assert_eq!(TareValue::new(0).unwrap().get(), 0);
+        assert_eq!(TareValue::new(9_999_999).unwrap(), TareValue::MAX);
         assert_eq!(
-            Command::SetTare(9_999_999).to_ascii_string().unwrap(),
-            "<T9999999>"
+            TareValue::new(10_000_000),
+            Err(Error::TareValue(10_000_000))
         );
+        assert_eq!(TareValue::try_from(1_234_567_u32).unwrap().get(), 1_234_567);
+        assert!(TareValue::try_from(99_999_999_u32).is_err());
+        assert_eq!(u32::from(TareValue::from(65_535_u16)), 65_535);
+    }
+
+    #[test]
+    fn validate_tare_like_encoding() {
+        let check = |value, tare_digits| {
+            let profile = DeviceProfile {
+                tare_digits,
+                ..DeviceProfile::default()
+            };
+            let encoded = TareValue::new(value)
+                .and_then(|val| Command::SetTare(val).to_ascii_string_with(&profile));
+            let result = validate_tare(value, &profile);
+            assert_eq!(result, encoded.map(|_| ()));
+            result
+        };
+        assert_eq!(check(0, 7), Ok(()));
+        assert_eq!(check(9_999_999, 7), Ok(()));
+        assert_eq!(check(10_000_000, 7), Err(Error::TareValue(10_000_000)));
+        assert_eq!(check(999, 3), Ok(()));
+        assert_eq!(check(1000, 3), Err(Error::TareValue(1000)));
+        assert_eq!(check(10_000_000, 8), Err(Error::TareValue(10_000_000)));
+    }
+
+    #[test]
+    fn borrowed_to_ascii_string() {
+        fn encode(frame: impl ToAsciiString) -> String {
+            frame
+                .to_ascii_string_with(&DeviceProfile::default())
+                .unwrap()
+        }
+        let cmd = Command::SetTare(TareValue::new(1500).unwrap());
+        let borrowed: &Command = &cmd;
+        assert_eq!(encode(borrowed), "<T0001500>");
+        let borrowed: &&WithAck<Command> = &&cmd.with_ack();
+        assert_eq!(encode(borrowed), "<t0001500>");
+        let frames: [&dyn ToAsciiString; 2] = [&Command::Tare, &Query::OnceOnChange];
+        assert_eq!(encode(frames[1]), "<B>");
         assert_eq!(
-            Command::SetTare(1_234_567).to_ascii_string().unwrap(),
-            "<T1234567>"
+            frames[0].to_ascii_frame(Terminator::CrLf).unwrap(),
+            "<T>\r\n"
         );
-        assert!(Command::SetTare(99_999_999).to_ascii_string().is_err());
+    }
+
+    #[test]
+    fn command_to_ascii_string() {
+        let tare = |v| Command::SetTare(TareValue::new(v).unwrap());
+        assert_eq!(Command::Tare.to_ascii_string().unwrap(), "<T>");
+        assert_eq!(Command::ClearTare.to_ascii_string().unwrap(), "<TC>");
+        assert_eq!(tare(0).to_ascii_string().unwrap(), "<T0000000>");
+        assert_eq!(tare(9_999_999).to_ascii_string().unwrap(), "<T9999999>");
+        assert_eq!(tare(1_234_567).to_ascii_string().unwrap(), "<T1234567>");
     }
 
     #[test]
     fn command_with_ack_to_ascii_string() {
+        let tare = |v| Command::SetTare(TareValue::new(v).unwrap());
         assert_eq!(Command::Tare.with_ack().to_ascii_string().unwrap(), "<t>");
         assert_eq!(
             Command::ClearTare.with_ack().to_ascii_string().unwrap(),
             "<tC>"
         );
+        assert_eq!(tare(0).with_ack().to_ascii_string().unwrap(), "<t0000000>");
         assert_eq!(
-            Command::SetTare(0).with_ack().to_ascii_string().unwrap(),
-            "<t0000000>"
+            tare(9_999_999).with_ack().to_ascii_string().unwrap(),
+            "<t9999999>"
         );
         assert_eq!(
-            Command::SetTare(9_999_999)
+            tare(1_234_567).with_ack().to_ascii_string().unwrap(),
+            "<t1234567>"
+        );
+    }
+
+    #[test]
+    fn command_encoded_len() {
+        let tare = |v| Command::SetTare(TareValue::new(v).unwrap());
+        let six = DeviceProfile {
+            tare_digits: 6,
+            ..DeviceProfile::default()
+        };
+        for cmd in [Command::Tare, Command::ClearTare, tare(0), tare(999_999)] {
+            let len = cmd.to_ascii_string().unwrap().len();
+            assert_eq!(cmd.encoded_len().unwrap(), len);
+            let len = cmd.with_ack().to_ascii_string_with(&six).unwrap().len();
+            assert_eq!(cmd.encoded_len_with(&six).unwrap(), len);
+        }
+        assert_eq!(tare(12).encoded_len().unwrap(), 10);
+        assert_eq!(tare(12).encoded_len_with(&six).unwrap(), 9);
+        assert_eq!(
+            tare(1_000_000).encoded_len_with(&six),
+            Err(Error::TareValue(1_000_000))
+        );
+    }
+
+    #[test]
+    fn command_to_ascii_frame() {
+        let frame = |t| Command::ClearTare.to_ascii_frame(t).unwrap();
+        assert_eq!(frame(Terminator::None), "<TC>");
+        assert_eq!(frame(Terminator::Cr), "<TC>\r");
+        assert_eq!(frame(Terminator::Lf), "<TC>\n");
+        assert_eq!(frame(Terminator::CrLf), "<TC>\r\n");
+        assert_eq!(frame(Terminator::Etx), "<TC>\x03");
+        assert_eq!(
+            Query::Once
                 .with_ack()
-                .to_ascii_string()
+                .to_ascii_frame(Terminator::Cr)
                 .unwrap(),
-            "<t9999999>"
+            "<a>\r"
         );
         assert_eq!(
-            Command::SetTare(1_234_567)
-                .with_ack()
-                .to_ascii_string()
+            Command::SetTare(TareValue::new(5).unwrap())
+                .to_ascii_frame(Terminator::Etx)
                 .unwrap(),
-            "<t1234567>"
+            "<T0000005>\x03"
+        );
+    }
+
+    #[test]
+    fn command_to_ascii_string_with_profile() {
+        let tare = |v| Command::SetTare(TareValue::new(v).unwrap());
+        let seven = DeviceProfile::default();
+        let six = DeviceProfile {
+            tare_digits: 6,
+            ..DeviceProfile::default()
+        };
+
+        assert_eq!(tare(0).to_ascii_string_with(&seven).unwrap(), "<T0000000>");
+        assert_eq!(tare(0).to_ascii_string_with(&six).unwrap(), "<T000000>");
+        assert_eq!(
+            tare(0).with_ack().to_ascii_string_with(&six).unwrap(),
+            "<t000000>"
+        );
+        assert_eq!(
+            tare(999_999).to_ascii_string_with(&six).unwrap(),
+            "<T999999>"
+        );
+        assert_eq!(
+            tare(1_000_000).to_ascii_string_with(&six),
+            Err(Error::TareValue(1_000_000))
+        );
+        assert_eq!(
+            tare(1_000_000).with_ack().to_ascii_string_with(&six),
+            Err(Error::TareValue(1_000_000))
+        );
+        assert_eq!(
+            tare(1_000_000).to_ascii_string_with(&seven).unwrap(),
+            "<T1000000>"
+        );
+        assert_eq!(Command::Tare.to_ascii_string_with(&six).unwrap(), "<T>");
+        assert_eq!(
+            Query::Once.to_ascii_string_with(&six).unwrap(),
+            Query::Once.to_ascii_string().unwrap()
+        );
+    }
+
+    #[test]
+    fn set_tare_from_str() {
+        let tare = |v| Command::SetTare(TareValue::new(v).unwrap());
+        let profile = DeviceProfile {
+            tare_decimals: 3,
+            ..DeviceProfile::default()
+        };
+        assert_eq!(Command::set_tare_str("1.5", &profile).unwrap(), tare(1500));
+        assert_eq!(Command::set_tare_str("1,5", &profile).unwrap(), tare(1500));
+        assert_eq!(Command::set_tare_str(" 2 ", &profile).unwrap(), tare(2000));
+        assert_eq!(Command::set_tare_str("0.001", &profile).unwrap(), tare(1));
+        assert_eq!(
+            Command::set_tare_str("1.2500", &profile).unwrap(),
+            tare(1250)
+        );
+        assert_eq!(
+            Command::set_tare_str("9999.999", &profile).unwrap(),
+            tare(9_999_999)
+        );
+        assert_eq!(
+            Command::set_tare_str("10000", &profile),
+            Err(Error::TareValue(10_000_000))
+        );
+        assert_eq!(
+            Command::set_tare_str("99999999999", &profile),
+            Err(Error::TareValue(u32::MAX))
+        );
+        assert_eq!(
+            Command::set_tare_str("1.0005", &profile),
+            Err(Error::TarePrecision("1.0005".into()))
+        );
+        for invalid in ["", "abc", "1.5kg", "-1", "1.2.3", ".5", "1 5", "+1"] {
+            assert_eq!(
+                Command::set_tare_str(invalid, &profile),
+                Err(Error::TareString(invalid.trim().into())),
+                "{invalid}"
+            );
+        }
+
+        let profile = DeviceProfile::default();
+        assert_eq!(Command::set_tare_str("15", &profile).unwrap(), tare(15));
+        assert!(Command::set_tare_str("1.5", &profile).is_err());
+    }
+
+    #[test]
+    fn tare_from_message() {
+        let tare = |v| Command::SetTare(TareValue::new(v).unwrap());
+        let profile = DeviceProfile {
+            tare_decimals: 3,
+            ..DeviceProfile::default()
+        };
+        let from = |value| Command::tare_from_message(&Message::new(1, value), &profile);
+        assert_eq!(from(3.1).unwrap(), tare(3100));
+        assert_eq!(from(0.0).unwrap(), tare(0));
+        assert_eq!(from(1.234_56).unwrap(), tare(1235));
+        assert_eq!(from(9999.999).unwrap(), tare(9_999_999));
+        assert_eq!(from(10_000.0), Err(Error::TareValue(10_000_000)));
+        assert_eq!(
+            from(-1.5),
+            Err(Error::NegativeTare(Weight::kilograms(-1.5)))
+        );
+
+        let grams = Message::new(1, 250.0).with_unit(Unit::Gram);
+        assert_eq!(
+            Command::tare_from_message(&grams, &profile).unwrap(),
+            tare(250)
+        );
+        let profile = DeviceProfile {
+            tare_unit: Unit::Gram,
+            ..DeviceProfile::default()
+        };
+        assert_eq!(
+            Command::tare_from_message(&Message::new(1, 1.5), &profile).unwrap(),
+            tare(1500)
+        );
+
+        let msg = Message::new(1, 12.4);
+        assert_eq!(
+            Command::tare_from_message(&msg, &DeviceProfile::default()).unwrap(),
+            tare(12)
         );
-        assert!(Command::SetTare(99_999_999)
-            .with_ack()
-            .to_ascii_string()
-            .is_err());
+    }
+
+    #[test]
+    fn command_sequence_to_ascii_string() {
+        let tare = |v| Command::SetTare(TareValue::new(v).unwrap());
+        let seq = CommandSequence(vec![Command::ClearTare, Command::Tare, tare(12)]);
+        assert_eq!(seq.to_ascii_string().unwrap(), "<TC><T><T0000012>");
+        assert_eq!(
+            seq.to_ascii_string_with(&DeviceProfile {
+                tare_digits: 6,
+                ..DeviceProfile::default()
+            })
+            .unwrap(),
+            "<TC><T><T000012>"
+        );
+
+        let seq: CommandSequence = [Command::Tare, tare(1_000_000)].into_iter().collect();
+        assert_eq!(
+            seq.to_ascii_string_with(&DeviceProfile {
+                tare_digits: 6,
+                ..DeviceProfile::default()
+            }),
+            Err(Error::TareValue(1_000_000))
+        );
+        assert_eq!(CommandSequence::default().to_ascii_string().unwrap(), "");
     }
 
     #[test]
@@ -406,4 +1747,74 @@ mod tests {
             "<b>"
         );
     }
+
+    #[test]
+    fn parse_query_from_str() {
+        assert_eq!(Query::from_str("<A>").unwrap(), Query::Once);
+        assert_eq!(Query::from_str("<B>").unwrap(), Query::OnceOnChange);
+        assert_eq!(
+            WithAck::<Query>::from_str("<a>").unwrap(),
+            Query::Once.with_ack()
+        );
+        assert_eq!(
+            WithAck::<Query>::from_str("<b>\r\n").unwrap(),
+            Query::OnceOnChange.with_ack()
+        );
+        assert_eq!(
+            Query::from_str("<a>"),
+            Err(Error::UnknownFrame("<a>".into()))
+        );
+        assert!(WithAck::<Query>::from_str("<A>").is_err());
+        assert_eq!(
+            Query::from_str("<C>"),
+            Err(Error::UnknownFrame("<C>".into()))
+        );
+        assert!(Query::from_str("<AA>").is_err());
+        assert!(Query::from_str("A").is_err());
+        assert!(Query::from_str("<>").is_err());
+        assert!(Query::from_str("").is_err());
+    }
+
+    #[test]
+    fn parse_command_from_str() {
+        let tare = |v| Command::SetTare(TareValue::new(v).unwrap());
+        assert_eq!(Command::from_str("<T>").unwrap(), Command::Tare);
+        assert_eq!(Command::from_str("<TC>").unwrap(), Command::ClearTare);
+        assert_eq!(Command::from_str("<T0001500>").unwrap(), tare(1500));
+        assert_eq!(Command::from_str("<T000150>").unwrap(), tare(150));
+        assert_eq!(
+            WithAck::<Command>::from_str("<tC>").unwrap(),
+            Command::ClearTare.with_ack()
+        );
+        assert_eq!(
+            WithAck::<Command>::from_str("<t9999999>").unwrap(),
+            tare(9_999_999).with_ack()
+        );
+        assert!(Command::from_str("<t>").is_err());
+        assert!(WithAck::<Command>::from_str("<T>").is_err());
+        assert!(Command::from_str("<TX>").is_err());
+        assert!(Command::from_str("<T-1>").is_err());
+        assert_eq!(
+            Command::from_str("<T99999999>"),
+            Err(Error::TareValue(99_999_999))
+        );
+        assert!(Command::from_str("<A>").is_err());
+    }
+
+    #[test]
+    fn command_and_query_round_trip() {
+        let tare = Command::SetTare(TareValue::new(42).unwrap());
+        for cmd in [Command::Tare, Command::ClearTare, tare] {
+            let s = cmd.to_ascii_string().unwrap();
+            assert_eq!(Command::from_str(&s).unwrap(), cmd);
+            let s = cmd.with_ack().to_ascii_string().unwrap();
+            assert_eq!(WithAck::<Command>::from_str(&s).unwrap(), cmd.with_ack());
+        }
+        for query in [Query::Once, Query::OnceOnChange] {
+            let s = query.to_ascii_string().unwrap();
+            assert_eq!(Query::from_str(&s).unwrap(), query);
+            let s = query.with_ack().to_ascii_string().unwrap();
+            assert_eq!(WithAck::<Query>::from_str(&s).unwrap(), query.with_ack());
+        }
+    }
 }
@@ -0,0 +1,160 @@
+//! Readings as Prometheus metrics.
+//!
+//! This requires the `metrics` feature.
+
+use std::{
+    fmt::Write as _,
+    io::{self, Read, Write},
+    net::TcpListener,
+    time::Duration,
+};
+
+use crate::{Clock, Message, Query, Terminal};
+
+/// How long [`serve`] waits for a client to send its request.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Name, help text and value of a gauge.
+type Gauge = (&'static str, &'static str, fn(&Message) -> f32);
+
+/// Renders the messages in the Prometheus text format:
+/// the weight in kilograms and each status flag (`0` or `1`)
+/// as gauges labeled with the balance ID.
+///
+/// ```
+/// # use soehnle_t3005::{metrics::render_metrics, Message};
+/// let text = render_metrics(&[Message::new(3, 1.5).with_standstill()]);
+/// assert!(text.contains("soehnle_weight_kg{id=\"3\"} 1.5\n"));
+/// assert!(text.contains("soehnle_standstill{id=\"3\"} 1\n"));
+/// ```
+#[must_use]
+pub fn render_metrics(messages: &[Message]) -> String {
+    let gauges: [Gauge; 5] = [
+        ("weight_kg", "The weight in kilograms.", |msg| {
+            msg.weight().to_kilograms()
+        }),
+        ("standstill", "The balance is at standstill.", |msg| {
+            flag(msg.status.standstill)
+        }),
+        ("over_load", "The balance is overloaded.", |msg| {
+            flag(msg.status.over_load)
+        }),
+        ("under_load", "The balance is underloaded.", |msg| {
+            flag(msg.status.under_load)
+        }),
+        (
+            "empty_message",
+            "The weight lies within the empty range.",
+            |msg| flag(msg.status.empty_message),
+        ),
+    ];
+    let mut out = String::new();
+    for (name, help, value) in gauges {
+        let _ = writeln!(out, "# HELP soehnle_{name} {help}");
+        let _ = writeln!(out, "# TYPE soehnle_{name} gauge");
+        for msg in messages {
+            let _ = writeln!(out, "soehnle_{name}{{id=\"{}\"}} {}", msg.id, value(msg));
+        }
+    }
+    out
+}
+
+const fn flag(set: bool) -> f32 {
+    if set {
+        1.0
+    } else {
+        0.0
+    }
+}
+
+/// Answers every HTTP request on `listener` with the metrics
+/// of a fresh [`Query::Once`] (see [`render_metrics`]).
+///
+/// Requests are handled one after another, the path is ignored.
+/// A client that doesn't send its request within a few seconds is dropped.
+/// If the terminal doesn't respond, no readings are reported.
+/// This only returns if accepting a connection fails.
+pub fn serve<P: Read + Write, C: Clock>(
+    listener: &TcpListener,
+    terminal: &mut Terminal<P, C>,
+) -> io::Result<()> {
+    for stream in listener.incoming() {
+        let mut stream = stream?;
+        let mut request = [0; 1024];
+        // An idle client mustn't block the exporter
+        if stream.set_read_timeout(Some(REQUEST_TIMEOUT)).is_err()
+            || stream.read(&mut request).is_err()
+        {
+            continue;
+        }
+        let messages: Vec<_> = terminal.query(Query::Once).into_iter().collect();
+        let body = render_metrics(&messages);
+        // A client that hung up doesn't stop the exporter
+        let _ = write!(
+            stream,
+            "HTTP/1.0 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\n\r\n{body}",
+            body.len()
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::TcpStream, thread};
+
+    use super::*;
+    use crate::{MockTerminal, Unit};
+
+    #[test]
+    fn render_gauges() {
+        let messages = [
+            Message::new(3, 1500.0)
+                .with_unit(Unit::Gram)
+                .with_standstill(),
+            Message::new(4, 9999.9).with_over_load(),
+        ];
+        assert_eq!(
+            render_metrics(&messages),
+            "\
+# HELP soehnle_weight_kg The weight in kilograms.
+# TYPE soehnle_weight_kg gauge
+soehnle_weight_kg{id=\"3\"} 1.5
+soehnle_weight_kg{id=\"4\"} 9999.9
+# HELP soehnle_standstill The balance is at standstill.
+# TYPE soehnle_standstill gauge
+soehnle_standstill{id=\"3\"} 1
+soehnle_standstill{id=\"4\"} 0
+# HELP soehnle_over_load The balance is overloaded.
+# TYPE soehnle_over_load gauge
+soehnle_over_load{id=\"3\"} 0
+soehnle_over_load{id=\"4\"} 1
+# HELP soehnle_under_load The balance is underloaded.
+# TYPE soehnle_under_load gauge
+soehnle_under_load{id=\"3\"} 0
+soehnle_under_load{id=\"4\"} 0
+# HELP soehnle_empty_message The weight lies within the empty range.
+# TYPE soehnle_empty_message gauge
+soehnle_empty_message{id=\"3\"} 0
+soehnle_empty_message{id=\"4\"} 0
+"
+        );
+    }
+
+    #[test]
+    fn serve_mock_reading() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            let mut terminal = Terminal::new(MockTerminal::new(Message::new(7, 2.5)));
+            serve(&listener, &mut terminal)
+        });
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.write_all(b"GET /metrics HTTP/1.0\r\n\r\n").unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.0 200 OK\r\n"));
+        assert!(response.ends_with("soehnle_empty_message{id=\"7\"} 0\n"));
+        assert!(response.contains("\nsoehnle_weight_kg{id=\"7\"} 2.5\n"));
+    }
+}
@@ -0,0 +1,275 @@
+//! A simulated terminal for testing applications without hardware.
+
+use std::{
+    collections::VecDeque,
+    io::{self, Read, Write},
+    str::FromStr,
+};
+
+use crate::{Command, Message, Query, WeightType, WithAck, ACK, NAK};
+
+/// A simulated terminal that can be used as the port of a
+/// [`Terminal`](crate::Terminal).
+///
+/// Queries are answered with the current [`MockTerminal::message`],
+/// commands with ACK if requested, unknown frames with NAK.
+/// Reading without a pending response fails with [`io::ErrorKind::TimedOut`],
+/// like a serial port with a read timeout.
+///
+/// Faults can be injected for the following requests:
+///
+/// ```
+/// # #[cfg(feature = "mock")] {
+/// # use soehnle_t3005::{Command, Error, MockTerminal, Terminal};
+/// let mut mock = MockTerminal::default();
+/// mock.fail_next(1);
+/// let mut terminal = Terminal::new(mock);
+/// assert_eq!(terminal.send_with_ack(Command::Tare), Err(Error::Nak));
+/// assert_eq!(terminal.send_with_ack(Command::Tare), Ok(()));
+/// # }
+/// ```
+///
+/// This requires the `mock` feature.
+#[derive(Debug)]
+pub struct MockTerminal {
+    /// The message sent in response to queries.
+    pub message: Message,
+    received: Vec<u8>,
+    requests: Vec<String>,
+    output: VecDeque<u8>,
+    fail: usize,
+    corrupt: bool,
+    drop: bool,
+}
+
+impl MockTerminal {
+    #[must_use]
+    pub const fn new(message: Message) -> Self {
+        Self {
+            message,
+            received: Vec::new(),
+            requests: Vec::new(),
+            output: VecDeque::new(),
+            fail: 0,
+            corrupt: false,
+            drop: false,
+        }
+    }
+
+    /// Answers the next `n` requests with NAK.
+    ///
+    /// Commands sent without ACK are not answered anyway, a failure
+    /// only counts for them and doesn't leave a stray NAK for the next request.
+    pub fn fail_next(&mut self, n: usize) {
+        self.fail = n;
+    }
+
+    /// Garbles the next response, so that it can't be parsed.
+    pub fn corrupt_next(&mut self) {
+        self.corrupt = true;
+    }
+
+    /// Doesn't answer the next request at all.
+    pub fn drop_next(&mut self) {
+        self.drop = true;
+    }
+
+    /// All frames received so far.
+    #[must_use]
+    pub fn requests(&self) -> &[String] {
+        &self.requests
+    }
+
+    fn handle(&mut self, frame: String) {
+        let response = self.respond(&frame);
+        self.requests.push(frame);
+        if std::mem::take(&mut self.drop) {
+            return;
+        }
+        let Some(mut response) = response else {
+            return;
+        };
+        if std::mem::take(&mut self.corrupt) {
+            if let Some(&(ACK | NAK)) = response.first() {
+                response = b"?\r\n".to_vec();
+            } else {
+                // the weight type
+                response[6] = b'?';
+            }
+        }
+        self.output.extend(response);
+    }
+
+    fn respond(&mut self, frame: &str) -> Option<Vec<u8>> {
+        if self.fail > 0 {
+            self.fail -= 1;
+            if Command::from_str(frame).is_ok() {
+                return None;
+            }
+            return Some(vec![NAK]);
+        }
+        if Query::from_str(frame).is_ok() || WithAck::<Query>::from_str(frame).is_ok() {
+            return Some(encode_message(&self.message).into_bytes());
+        }
+        if Command::from_str(frame).is_ok() {
+            return None;
+        }
+        if WithAck::<Command>::from_str(frame).is_ok() {
+            return Some(vec![ACK]);
+        }
+        Some(vec![NAK])
+    }
+}
+
+impl Default for MockTerminal {
+    /// Reports 0 kg on balance 1.
+    fn default() -> Self {
+        Self::new(Message::new(1, 0.0))
+    }
+}
+
+impl Read for MockTerminal {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.output.is_empty() {
+            return Err(io::ErrorKind::TimedOut.into());
+        }
+        let n = buf.len().min(self.output.len());
+        for (slot, b) in buf.iter_mut().zip(self.output.drain(..n)) {
+            *slot = b;
+        }
+        Ok(n)
+    }
+}
+
+impl Write for MockTerminal {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.received.extend_from_slice(buf);
+        while let Some(end) = self.received.iter().position(|b| *b == b'>') {
+            let bytes: Vec<u8> = self.received.drain(..=end).collect();
+            // Skips control bytes like ENQ
+            let frame = String::from_utf8_lossy(&bytes)
+                .trim_matches(|c: char| c.is_whitespace() || c.is_ascii_control())
+                .to_owned();
+            self.handle(frame);
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Encodes a message in the `30XX EDV Standard` format.
+fn encode_message(msg: &Message) -> String {
+    let bit = |flag| if flag { '1' } else { '0' };
+    let status = &msg.status;
+    let weight_type = match msg.weight_type {
+        WeightType::Net => 'N',
+        WeightType::Gross => 'B',
+    };
+    let value = msg.value.to_string().replace('.', ",");
+    format!(
+        "{}{}{}{}{:02}{weight_type}{value:>10} {}\r\n",
+        bit(status.under_load),
+        bit(status.over_load),
+        bit(status.standstill),
+        bit(status.empty_message),
+        msg.id,
+        msg.unit,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::{Error, RetryPolicy, Terminal, TerminalConfig, Unit};
+
+    fn retrying(mock: MockTerminal, max_attempts: u8) -> Terminal<MockTerminal> {
+        let config = TerminalConfig {
+            retry: RetryPolicy::new(max_attempts, Duration::ZERO),
+            ..TerminalConfig::default()
+        };
+        Terminal::with_config(mock, config)
+    }
+
+    #[test]
+    fn answer_queries_and_commands() {
+        let msg = Message::new(7, -2.5)
+            .with_standstill()
+            .with_unit(Unit::Gram);
+        let mut terminal = Terminal::new(MockTerminal::new(msg));
+        assert_eq!(terminal.query(Query::Once), Ok(msg));
+        terminal.send(Command::Tare).unwrap();
+        terminal.send_with_ack(Command::ClearTare).unwrap();
+        assert_eq!(terminal.into_inner().requests(), ["<A>", "<T>", "<tC>"]);
+    }
+
+    #[test]
+    fn ignore_enquiries() {
+        let config = TerminalConfig {
+            enquire: true,
+            ..TerminalConfig::default()
+        };
+        let mut terminal = Terminal::with_config(MockTerminal::default(), config);
+        assert!(terminal.query(Query::Once).is_ok());
+        terminal.send_with_ack(Command::Tare).unwrap();
+        assert_eq!(terminal.into_inner().requests(), ["<A>", "<t>"]);
+    }
+
+    #[test]
+    fn fail_next_requests() {
+        let mut mock = MockTerminal::default();
+        mock.fail_next(2);
+        let mut terminal = retrying(mock, 3);
+        terminal.send_with_ack(Command::Tare).unwrap();
+        assert_eq!(terminal.into_inner().requests().len(), 3);
+
+        let mut mock = MockTerminal::default();
+        mock.fail_next(2);
+        let mut terminal = retrying(mock, 2);
+        assert_eq!(terminal.query(Query::Once), Err(Error::Nak));
+
+        let mut mock = MockTerminal::default();
+        mock.fail_next(1);
+        let mut terminal = Terminal::new(mock);
+        terminal.send(Command::Tare).unwrap();
+        assert_eq!(terminal.query(Query::Once), Ok(Message::new(1, 0.0)));
+    }
+
+    #[test]
+    fn drop_next_response() {
+        let mut mock = MockTerminal::default();
+        mock.drop_next();
+        let mut terminal = Terminal::new(mock);
+        assert_eq!(terminal.query(Query::Once), Err(Error::Timeout));
+        assert!(terminal.query(Query::Once).is_ok());
+
+        let mut mock = MockTerminal::default();
+        mock.drop_next();
+        let mut terminal = retrying(mock, 2);
+        assert!(terminal.send_with_ack(Command::Tare).is_ok());
+    }
+
+    #[test]
+    fn corrupt_next_response() {
+        let mut mock = MockTerminal::default();
+        mock.corrupt_next();
+        let mut terminal = Terminal::new(mock);
+        assert_eq!(
+            terminal.query(Query::Once),
+            Err(Error::WeightType("?".into()))
+        );
+        assert!(terminal.query(Query::Once).is_ok());
+
+        let mut mock = MockTerminal::default();
+        mock.corrupt_next();
+        let mut terminal = Terminal::new(mock);
+        assert!(matches!(
+            terminal.send_with_ack(Command::Tare),
+            Err(Error::MessageLength(_))
+        ));
+    }
+}
@@ -0,0 +1,173 @@
+use crate::{Command, Error, Message, Result, Unit, Weight};
+
+/// Properties of a specific terminal model or configuration.
+///
+/// The [`Default`] profile matches the T3005 with its 7 digit tare field.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeviceProfile {
+    /// Number of digits of the tare field in `SetTare` commands.
+    pub tare_digits: usize,
+    /// Number of decimal places of the tare field,
+    /// e.g. with 3 decimals `0001500` means 1.5 (kg).
+    pub tare_decimals: u32,
+    /// The unit of the tare field, see [`Command::tare_from_message`].
+    pub tare_unit: Unit,
+    /// The largest weight the terminal can plausibly report.
+    ///
+    /// Messages exceeding it (in either direction) are rejected
+    /// as corrupt by [`DeviceProfile::check_plausible`].
+    ///
+    /// Only a [`Terminal`](crate::Terminal) and a [`Session`](crate::Session)
+    /// apply the check to received messages; [`Protocol`](crate::Protocol),
+    /// [`Message::from_str`](std::str::FromStr::from_str) and
+    /// [`MultiBalanceReader`](crate::MultiBalanceReader) don't know the profile.
+    pub max_plausible_weight: Option<Weight>,
+    /// The smallest step the display of the terminal shows, e.g. 5 g,
+    /// see [`Weight::as_displayed`].
+    pub display_resolution: Option<Weight>,
+    /// The terminal accepts [`Command::SetTare`].
+    pub supports_set_tare: bool,
+    /// The terminal accepts [`Command::ClearTare`].
+    pub supports_clear_tare: bool,
+}
+
+impl DeviceProfile {
+    /// The largest tare value that fits into the tare field.
+    #[must_use]
+    pub fn max_tare_value(&self) -> u32 {
+        u32::try_from(self.tare_digits)
+            .ok()
+            .and_then(|digits| 10_u32.checked_pow(digits))
+            .map_or(u32::MAX, |limit| limit - 1)
+    }
+
+    /// Whether the terminal accepts the command.
+    ///
+    /// Unsupported commands are rejected with [`Error::UnsupportedCommand`]
+    /// when they are encoded.
+    #[must_use]
+    pub const fn supports(&self, cmd: &Command) -> bool {
+        match cmd {
+            Command::Tare => true,
+            Command::ClearTare => self.supports_clear_tare,
+            Command::SetTare(_) => self.supports_set_tare,
+        }
+    }
+
+    /// Rejects messages whose weight exceeds the `max_plausible_weight`
+    /// or isn't finite.
+    ///
+    /// ```
+    /// # use std::str::FromStr;
+    /// # use soehnle_t3005::{DeviceProfile, Error, Message, Weight};
+    /// let profile = DeviceProfile {
+    ///     max_plausible_weight: Some(Weight::kilograms(50.0)),
+    ///     ..DeviceProfile::default()
+    /// };
+    /// let msg = Message::from_str("000101N   900000,0 kg").unwrap();
+    /// assert!(matches!(
+    ///     profile.check_plausible(msg),
+    ///     Err(Error::ValueOutOfRange(_))
+    /// ));
+    /// ```
+    pub fn check_plausible(&self, msg: Message) -> Result<Message> {
+        match self.max_plausible_weight {
+            _ if !msg.value.is_finite() => Err(Error::ValueOutOfRange(msg.weight())),
+            Some(max) if msg.weight().to_unit(max.unit).value.abs() > max.value => {
+                Err(Error::ValueOutOfRange(msg.weight()))
+            }
+            _ => Ok(msg),
+        }
+    }
+}
+
+impl Default for DeviceProfile {
+    fn default() -> Self {
+        Self {
+            tare_digits: 7,
+            tare_decimals: 0,
+            tare_unit: Unit::Kilogram,
+            max_plausible_weight: None,
+            display_resolution: None,
+            supports_set_tare: true,
+            supports_clear_tare: true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{TareValue, ToAsciiString, Unit};
+
+    #[test]
+    fn reject_unsupported_commands() {
+        let profile = DeviceProfile {
+            supports_set_tare: false,
+            supports_clear_tare: false,
+            ..DeviceProfile::default()
+        };
+        let set_tare = Command::SetTare(TareValue::new(5).unwrap());
+        assert!(profile.supports(&Command::Tare));
+        assert!(!profile.supports(&set_tare));
+        assert_eq!(Command::Tare.to_ascii_string_with(&profile).unwrap(), "<T>");
+        assert_eq!(
+            set_tare.with_ack().to_ascii_string_with(&profile),
+            Err(Error::UnsupportedCommand(set_tare))
+        );
+        assert_eq!(
+            Command::ClearTare.encoded_len_with(&profile),
+            Err(Error::UnsupportedCommand(Command::ClearTare))
+        );
+        assert!(DeviceProfile::default().supports(&set_tare));
+    }
+
+    #[test]
+    fn max_tare_value() {
+        assert_eq!(DeviceProfile::default().max_tare_value(), 9_999_999);
+        let profile = DeviceProfile {
+            tare_digits: 6,
+            ..DeviceProfile::default()
+        };
+        assert_eq!(profile.max_tare_value(), 999_999);
+        let profile = DeviceProfile {
+            tare_digits: 12,
+            ..DeviceProfile::default()
+        };
+        assert_eq!(profile.max_tare_value(), u32::MAX);
+    }
+
+    #[test]
+    fn check_plausible_weight() {
+        let msg = Message::new(1, 900_000.0);
+        assert_eq!(DeviceProfile::default().check_plausible(msg), Ok(msg));
+
+        let profile = DeviceProfile {
+            max_plausible_weight: Some(Weight::kilograms(50.0)),
+            ..DeviceProfile::default()
+        };
+        let at_max = Message::new(1, 50.0);
+        assert_eq!(profile.check_plausible(at_max), Ok(at_max));
+        assert_eq!(
+            profile.check_plausible(at_max.with_unit(Unit::Gram)),
+            Ok(at_max.with_unit(Unit::Gram))
+        );
+        assert_eq!(
+            profile.check_plausible(Message::new(1, 50.1)),
+            Err(Error::ValueOutOfRange(Weight::kilograms(50.1)))
+        );
+        assert_eq!(
+            profile.check_plausible(Message::new(1, -51.0)),
+            Err(Error::ValueOutOfRange(Weight::kilograms(-51.0)))
+        );
+        assert_eq!(
+            profile.check_plausible(msg),
+            Err(Error::ValueOutOfRange(Weight::kilograms(900_000.0)))
+        );
+        let nan = Message::new(1, f32::NAN);
+        assert!(matches!(
+            DeviceProfile::default().check_plausible(nan),
+            Err(Error::ValueOutOfRange(_))
+        ));
+    }
+}
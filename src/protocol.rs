@@ -0,0 +1,1265 @@
+use std::{
+    borrow::Cow,
+    cmp::Ordering,
+    ops::Range,
+    str::{self, FromStr},
+};
+
+use crate::{
+    parse_command, parse_query, Context, Echo, Error, Message, PartialMessage, Response, Result,
+    Status, Unit, WeightType, ACK, ENQ, NAK,
+};
+
+const MIN_MESSAGE_LEN: usize = 7;
+const MAX_MESSAGE_LEN: usize = 27;
+/// A rounded value has up to three more bytes: a leading `0`, a carry and a sign.
+const ROUND_BUF_LEN: usize = MAX_MESSAGE_LEN + 3;
+
+/// How the unit of a value is transmitted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum UnitField {
+    /// The unit symbol follows the value.
+    /// Values without a unit are taken as kilograms.
+    #[default]
+    Suffix,
+    /// The terminal does not transmit a unit,
+    /// the value extends to the end of the frame.
+    Absent(Unit),
+}
+
+/// How the terminal signals standstill.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Standstill {
+    /// The third status bit.
+    #[default]
+    StatusBit,
+    /// A symbol at the end of the frame, the status bit is ignored.
+    Symbol(char),
+}
+
+/// Where the sign of a value is transmitted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SignFormat {
+    /// The sign precedes the digits (`-3,9`).
+    #[default]
+    Leading,
+    /// The sign follows the digits (`3,9-`).
+    Trailing,
+    /// The sign has its own column in the value field, counted from the first
+    /// character after the weight type, e.g. `N-     3,9 kg` with column `0`.
+    /// A blank column means positive.
+    SeparateColumn(usize),
+}
+
+/// How the fields of a frame are separated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FieldLayout {
+    /// Every field has a fixed position and width.
+    #[default]
+    Fixed,
+    /// The fields `status`, `id`, `value` and, optionally, `unit`
+    /// are separated by the given character, e.g. `0001;01;3,5;kg`.
+    ///
+    /// If [`Protocol::address_field_len`] isn't `0`, an address field comes first.
+    /// The weight type isn't transmitted, all values are net.
+    Delimited(char),
+}
+
+/// The order of the status and the balance ID at the start of a frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FieldOrder {
+    /// `0010` `01` `N   3,5 kg`
+    #[default]
+    StatusFirst,
+    /// `01` `0010` `N   3,5 kg`
+    IdFirst,
+}
+
+impl FieldOrder {
+    /// The byte ranges of the status and the ID in a fixed layout frame,
+    /// after the address.
+    pub(crate) const fn ranges(self) -> (Range<usize>, Range<usize>) {
+        match self {
+            Self::StatusFirst => (0..4, 4..6),
+            Self::IdFirst => (2..6, 0..2),
+        }
+    }
+}
+
+/// Describes the format of the frames sent by the terminal.
+///
+/// The [`Default`] is the `30XX EDV Standard` format.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Protocol {
+    pub layout: FieldLayout,
+    /// Applies to both layouts, a delimited frame then starts with the ID
+    /// (after the address, if any).
+    pub order: FieldOrder,
+    pub unit: UnitField,
+    pub standstill: Standstill,
+    pub sign: SignFormat,
+    /// Removes CR and LF characters inside a frame before the fields are split.
+    ///
+    /// A [`Terminal`](crate::Terminal) then only ends frames at LF.
+    pub strip_line_breaks: bool,
+    /// Rounds parsed values to the given number of decimal places
+    /// (half to even), `None` keeps all transmitted digits.
+    pub round_to_decimals: Option<usize>,
+    /// Number of digits of a device address in front of the status,
+    /// `0` if the frames have no address.
+    pub address_field_len: usize,
+    /// The terminal echoes received commands and queries, they are
+    /// parsed as [`Response::Echo`].
+    pub echo: bool,
+    /// Frames that have a status and balance ID but no value
+    /// are parsed as [`Response::StatusOnly`] instead of failing
+    /// with [`Error::EmptyValue`].
+    pub status_only_frames: bool,
+    /// Bytes that are removed from the value field before the number is parsed.
+    ///
+    /// By default these are only spaces, which pad the right-aligned value.
+    /// Add e.g. `b'W'` for terminals that put a marker next to the digits.
+    /// Independent of this, the unit is split off according to [`Protocol::unit`]
+    /// and a `,` is taken as decimal separator.
+    pub ignored_value_bytes: Vec<u8>,
+    /// The number of buffered bytes without a frame end after which
+    /// they are discarded as garbage, see [`Error::FrameTooLong`].
+    pub max_frame_len: usize,
+    /// Control bytes that wrap frames, e.g. STX (`0x02`) and ETX (`0x03`).
+    /// They are stripped from both ends of a frame, like whitespace.
+    ///
+    /// A received frame ends at the first framing byte after its content,
+    /// so framed frames don't need a line break.
+    pub framing_bytes: Vec<u8>,
+    /// The byte that acknowledges a request (ASCII ACK by default).
+    pub ack: u8,
+    /// The byte that rejects a request (ASCII NAK by default).
+    pub nak: u8,
+    /// The byte that asks the terminal to transmit (ASCII ENQ by default),
+    /// see [`TerminalConfig::enquire`](crate::TerminalConfig::enquire).
+    pub enq: u8,
+    /// A byte the terminal sends instead of a response while it is busy,
+    /// e.g. XOFF (`0x13`) while waiting for a printer.
+    /// It is reported as [`Error::DeviceBusy`].
+    pub busy: Option<u8>,
+}
+
+impl Default for Protocol {
+    fn default() -> Self {
+        Self {
+            layout: FieldLayout::default(),
+            order: FieldOrder::default(),
+            unit: UnitField::default(),
+            standstill: Standstill::default(),
+            sign: SignFormat::default(),
+            strip_line_breaks: false,
+            round_to_decimals: None,
+            address_field_len: 0,
+            echo: false,
+            status_only_frames: false,
+            ignored_value_bytes: vec![b' '],
+            max_frame_len: 1024,
+            framing_bytes: Vec::new(),
+            ack: ACK,
+            nak: NAK,
+            enq: ENQ,
+            busy: None,
+        }
+    }
+}
+
+impl Protocol {
+    /// Parses a single weight message.
+    pub fn parse_message(&self, s: &str) -> Result<Message> {
+        let normalized = self.normalize(s);
+        let s = normalized.as_ref();
+        if let FieldLayout::Delimited(delimiter) = self.layout {
+            return self.parse_delimited(s, delimiter);
+        }
+        let address_len = self.address_field_len;
+        if (s.len() > MAX_MESSAGE_LEN + address_len) || (s.len() < MIN_MESSAGE_LEN + address_len) {
+            return Err(Error::MessageLength(s.into()));
+        }
+        if !s.is_ascii() {
+            return Err(Error::NonAsciiStr(s.into()));
+        }
+        let (address, s) = s.split_at(address_len);
+        let address = parse_address(address)?;
+        let (status, id) = self.order.ranges();
+        let (status, id, netto) = (&s[status], &s[id], &s[6..]);
+        let weight_type = WeightType::from_str(&netto[..1])?;
+        let mut status = Status::from_str(status)?;
+        let (value, unit, standstill) = self.parse_value(netto)?;
+        if let Some(standstill) = standstill {
+            status.standstill = standstill;
+        }
+        let id = parse_id(id)?;
+        Ok(Message {
+            status,
+            id,
+            weight_type,
+            value,
+            unit,
+            address,
+        })
+    }
+
+    fn parse_delimited(&self, s: &str, delimiter: char) -> Result<Message> {
+        if !s.is_ascii() {
+            return Err(Error::NonAsciiStr(s.into()));
+        }
+        let mut fields: Vec<&str> = s.split(delimiter).map(str::trim).collect();
+        let address = if self.address_field_len > 0 {
+            parse_address(fields.remove(0))?
+        } else {
+            None
+        };
+        let (first, second, value, unit) = match fields[..] {
+            [first, second, value] => (first, second, value, self.unit),
+            [first, second, value, unit] => (
+                first,
+                second,
+                value,
+                UnitField::Absent(Unit::from_str(unit)?),
+            ),
+            _ => return Err(Error::MessageLength(s.into())),
+        };
+        let (status, id) = match self.order {
+            FieldOrder::StatusFirst => (first, second),
+            FieldOrder::IdFirst => (second, first),
+        };
+        let mut status = Status::from_str(status)?;
+        let (value, unit, standstill) = self.parse_value_field(value, unit, value)?;
+        if let Some(standstill) = standstill {
+            status.standstill = standstill;
+        }
+        Ok(Message {
+            status,
+            id: parse_id(id)?,
+            weight_type: WeightType::Net,
+            value,
+            unit,
+            address,
+        })
+    }
+
+    /// Parses as many fields of a weight message as possible,
+    /// e.g. to find out which balance sent a frame with a garbled value.
+    #[must_use]
+    pub fn parse_partial(&self, s: &str) -> PartialMessage {
+        let normalized = self.normalize(s);
+        let s = normalized.as_ref();
+        if let FieldLayout::Delimited(delimiter) = self.layout {
+            return self.parse_partial_delimited(s, delimiter);
+        }
+        let address_len = self.address_field_len;
+        if s.len() > MAX_MESSAGE_LEN + address_len || !s.is_ascii() {
+            return PartialMessage::default();
+        }
+        let address = s
+            .get(..address_len)
+            .and_then(|a| parse_address(a).ok().flatten());
+        let s = s.get(address_len..).unwrap_or_default();
+        let (status, id) = self.order.ranges();
+        let mut status = s.get(status).and_then(|s| Status::from_str(s).ok());
+        let id = s.get(id).and_then(|id| parse_id(id).ok());
+        let value = s.get(6..).and_then(|netto| self.parse_value(netto).ok());
+        if let (Some(status), Some((_, _, Some(standstill)))) = (&mut status, value) {
+            status.standstill = standstill;
+        }
+        PartialMessage {
+            status,
+            id,
+            value: value.map(|(value, ..)| value),
+            address,
+        }
+    }
+
+    fn parse_partial_delimited(&self, s: &str, delimiter: char) -> PartialMessage {
+        if !s.is_ascii() {
+            return PartialMessage::default();
+        }
+        let mut fields = s.split(delimiter).map(str::trim);
+        let address = if self.address_field_len > 0 {
+            fields.next().and_then(|a| parse_address(a).ok().flatten())
+        } else {
+            None
+        };
+        let (first, second) = (fields.next(), fields.next());
+        let (status, id) = match self.order {
+            FieldOrder::StatusFirst => (first, second),
+            FieldOrder::IdFirst => (second, first),
+        };
+        let mut status = status.and_then(|s| Status::from_str(s).ok());
+        let id = id.and_then(|id| parse_id(id).ok());
+        let value = fields.next().and_then(|value| {
+            let unit = match fields.next() {
+                Some(unit) => UnitField::Absent(Unit::from_str(unit).ok()?),
+                None => self.unit,
+            };
+            self.parse_value_field(value, unit, value).ok()
+        });
+        if let (Some(status), Some((_, _, Some(standstill)))) = (&mut status, value) {
+            status.standstill = standstill;
+        }
+        PartialMessage {
+            status,
+            id,
+            value: value.map(|(value, ..)| value),
+            address,
+        }
+    }
+
+    pub(crate) fn normalize<'a>(&self, s: &'a str) -> Cow<'a, str> {
+        let s = self.strip_framing(s);
+        if self.strip_line_breaks && s.contains(['\r', '\n']) {
+            Cow::Owned(s.replace(['\r', '\n'], ""))
+        } else {
+            Cow::Borrowed(s)
+        }
+    }
+
+    fn strip_framing<'a>(&self, s: &'a str) -> &'a str {
+        s.trim_matches(|c: char| {
+            c.is_whitespace() || u8::try_from(c).is_ok_and(|b| self.framing_bytes.contains(&b))
+        })
+    }
+
+    /// Parses the value, the unit and the standstill symbol (if configured)
+    /// that follow the weight type.
+    pub(crate) fn parse_value(&self, netto: &str) -> Result<(f32, Unit, Option<bool>)> {
+        let value = netto.get(1..).ok_or(Error::EmptyValue)?;
+        self.parse_value_field(value, self.unit, netto)
+    }
+
+    /// Parses a value field, `field` is reported in errors.
+    fn parse_value_field(
+        &self,
+        value: &str,
+        unit: UnitField,
+        field: &str,
+    ) -> Result<(f32, Unit, Option<bool>)> {
+        let (value, standstill) = match self.standstill {
+            Standstill::StatusBit => (value, None),
+            Standstill::Symbol(symbol) => {
+                let value = value.trim_end();
+                let stripped = value.strip_suffix(symbol);
+                (stripped.unwrap_or(value), Some(stripped.is_some()))
+            }
+        };
+        let (value, unit) = match unit {
+            UnitField::Suffix => {
+                let (value, unit) = Unit::split_suffix(value.trim_end());
+                (value, unit.unwrap_or_default())
+            }
+            UnitField::Absent(unit) => (value, unit),
+        };
+        let (value, sign, sign_column) = match self.sign {
+            SignFormat::Leading => (value, None, None),
+            SignFormat::Trailing => {
+                let value = value.trim_end();
+                match value.strip_suffix(['-', '+']) {
+                    Some(digits) => (digits, value.bytes().last(), None),
+                    None => (value, None, None),
+                }
+            }
+            SignFormat::SeparateColumn(column) => match value.as_bytes().get(column) {
+                Some(&sign @ (b'-' | b'+')) => (value, Some(sign), Some(column)),
+                _ => (value, None, None),
+            },
+        };
+        let mut buf = [0; MAX_MESSAGE_LEN];
+        let v = compact(
+            sign.into_iter().chain(
+                value
+                    .bytes()
+                    .enumerate()
+                    .filter(|(i, b)| {
+                        Some(*i) != sign_column && !self.ignored_value_bytes.contains(b)
+                    })
+                    .map(|(_, b)| if b == b',' { b'.' } else { b }),
+            ),
+            &mut buf,
+        )
+        .ok_or_else(|| Error::MessageLength(field.into()))?;
+        if v.is_empty() {
+            return Err(Error::EmptyValue);
+        }
+        let mut rounded = [0; ROUND_BUF_LEN];
+        // Anything but a plain decimal number is left unrounded to the
+        // float parser, which rejects garbage like `1-9.96`
+        let v = match self.round_to_decimals {
+            Some(decimals) => round_half_even(v, decimals, &mut rounded).unwrap_or(v),
+            None => v,
+        };
+        let value: f32 = v.parse().context(Error::BalanceValue, field)?;
+        if !value.is_finite() {
+            return Err(Error::NonFiniteValue(field.into()));
+        }
+        Ok((value, unit, standstill))
+    }
+
+    /// Parses an ACK, a NAK or a weight message.
+    ///
+    /// Only the first character of a frame is compared to the ACK and NAK bytes.
+    /// A byte above `0x7F` matches the char with the same code, e.g. `'\u{86}'` for `0x86`.
+    /// Leading NUL bytes, which some terminals send as padding, are skipped.
+    pub fn parse_response(&self, s: &str) -> Result<Response> {
+        let s = self
+            .strip_framing(s.trim_start_matches(|c: char| u8::try_from(c).is_ok_and(is_padding)));
+        let Some(first) = s.chars().next() else {
+            return Err(Error::MessageLength(s.into()));
+        };
+        if first == char::from(self.ack) {
+            return Ok(Response::Ack);
+        }
+        if first == char::from(self.nak) {
+            return Ok(Response::Nak);
+        }
+        if self.busy.map(char::from) == Some(first) {
+            return Err(Error::DeviceBusy);
+        }
+        if self.echo && s.starts_with('<') {
+            let echo = parse_query(s)
+                .map(|(query, _)| Echo::Query(query))
+                .or_else(|_| parse_command(s).map(|(cmd, _)| Echo::Command(cmd)))?;
+            return Ok(Response::Echo(echo));
+        }
+        match self.parse_message(s) {
+            Err(Error::EmptyValue | Error::MessageLength(_)) if self.status_only_frames => {
+                match self.parse_partial(s) {
+                    PartialMessage {
+                        status: Some(status),
+                        id: Some(id),
+                        value: None,
+                        ..
+                    } => Ok(Response::StatusOnly { id, status }),
+                    _ => self.parse_message(s).map(Response::Message),
+                }
+            }
+            result => result.map(Response::Message),
+        }
+    }
+}
+
+/// Parses a transmitted value and counts its decimal places,
+/// which tell the precision the terminal declares.
+///
+/// Trailing zeros are significant, so the value can be shown exactly as received:
+///
+/// ```
+/// # use soehnle_t3005::parse_value;
+/// assert_eq!(parse_value("  3,10"), Ok((3.1, 2)));
+/// assert_eq!(parse_value("3,1"), Ok((3.1, 1)));
+/// assert_eq!(parse_value("-12"), Ok((-12.0, 0)));
+/// ```
+pub fn parse_value(s: &str) -> Result<(f32, usize)> {
+    let mut buf = [0; MAX_MESSAGE_LEN];
+    if s.len() > buf.len() {
+        return Err(Error::MessageLength(s.into()));
+    }
+    let v = compact(
+        s.bytes().filter_map(|b| match b {
+            b' ' => None,
+            b',' => Some(b'.'),
+            b => Some(b),
+        }),
+        &mut buf,
+    )
+    .ok_or_else(|| Error::MessageLength(s.into()))?;
+    if v.is_empty() {
+        return Err(Error::EmptyValue);
+    }
+    let value: f32 = v.parse().context(Error::BalanceValue, s)?;
+    if !value.is_finite() {
+        return Err(Error::NonFiniteValue(s.into()));
+    }
+    let decimals = v.split_once('.').map_or(0, |(_, frac)| frac.len());
+    Ok((value, decimals))
+}
+
+/// Rounds the decimal number `v` to `decimals` places, ties to even.
+///
+/// This works on the digits, so that e.g. `3.145` is a tie
+/// although it has no exact binary representation.
+/// Returns `None` if `v` isn't an optionally signed decimal number.
+fn round_half_even<'a>(
+    v: &str,
+    decimals: usize,
+    buf: &'a mut [u8; ROUND_BUF_LEN],
+) -> Option<&'a str> {
+    let digits = v.strip_prefix(['-', '+']).unwrap_or(v);
+    let sign = &v[..v.len() - digits.len()];
+    let (int, frac) = digits.split_once('.').unwrap_or((digits, ""));
+    let is_digits = |s: &str| s.bytes().all(|b| b.is_ascii_digit());
+    if !is_digits(int) || !is_digits(frac) || int.len() + frac.len() == 0 {
+        return None;
+    }
+    // `,5` means `0,5`
+    let int = if int.is_empty() { "0" } else { int };
+    let (kept, dropped) = frac.split_at(decimals.min(frac.len()));
+    let last = kept
+        .bytes()
+        .last()
+        .or_else(|| int.bytes().last())
+        .unwrap_or(b'0');
+    let round_up = match dropped.bytes().next().map(|next| next.cmp(&b'5')) {
+        Some(Ordering::Greater) => true,
+        Some(Ordering::Equal) => {
+            dropped.bytes().skip(1).any(|b| b != b'0') || (last - b'0') % 2 == 1
+        }
+        Some(Ordering::Less) | None => false,
+    };
+    // The first two bytes are reserved for the sign and a carry
+    let mut len = 2;
+    for part in [int, if kept.is_empty() { "" } else { "." }, kept] {
+        buf[len..len + part.len()].copy_from_slice(part.as_bytes());
+        len += part.len();
+    }
+    let mut start = 2;
+    if round_up {
+        let mut i = len - 1;
+        loop {
+            match buf[i] {
+                b'9' => buf[i] = b'0',
+                b'.' => {}
+                _ => {
+                    buf[i] += 1;
+                    break;
+                }
+            }
+            if i == 2 {
+                buf[1] = b'1';
+                start = 1;
+                break;
+            }
+            i -= 1;
+        }
+    }
+    if let Some(&sign) = sign.as_bytes().first() {
+        start -= 1;
+        buf[start] = sign;
+    }
+    str::from_utf8(&buf[start..len]).ok()
+}
+
+fn parse_address(address: &str) -> Result<Option<u8>> {
+    if address.is_empty() {
+        return Ok(None);
+    }
+    address.parse().map(Some).context(Error::Address, address)
+}
+
+pub(crate) fn parse_id(id: &str) -> Result<u8> {
+    let mut buf = [0; 2];
+    let digits = compact(id.bytes().filter(|b| *b != b'W'), &mut buf)
+        .ok_or_else(|| Error::BalanceIdTooLong(id.into()))?;
+    digits.parse().context(Error::BalanceId, id)
+}
+
+/// Whitespace or NUL bytes in front of a response.
+pub(crate) const fn is_padding(b: u8) -> bool {
+    b == 0 || b.is_ascii_whitespace()
+}
+
+/// Copies the (ASCII) `bytes` into `buf` without allocating,
+/// `None` if they don't fit.
+fn compact(mut bytes: impl Iterator<Item = u8>, buf: &mut [u8]) -> Option<&str> {
+    let mut len = 0;
+    for (slot, b) in buf.iter_mut().zip(bytes.by_ref()) {
+        *slot = b;
+        len += 1;
+    }
+    if bytes.next().is_some() {
+        return None;
+    }
+    Some(str::from_utf8(&buf[..len]).unwrap_or_default())
+}
+
+#[cfg(test)]
+#[allow(clippy::float_cmp)]
+mod tests {
+    use super::*;
+    use crate::{Command, Query};
+
+    /// Run with `cargo test --release -- --ignored --nocapture parse_throughput`.
+    #[test]
+    #[ignore = "timing, only meaningful in a release build"]
+    fn parse_throughput() {
+        let protocol = Protocol::default();
+        let frames = [
+            "000101N        3,1 kg",
+            "001001N       -250 g",
+            "0000W1B   12345,67 lb",
+        ];
+        let start = std::time::Instant::now();
+        let mut sum = 0.0;
+        for i in 0..4_000_000 {
+            sum += protocol
+                .parse_message(frames[i % frames.len()])
+                .unwrap()
+                .value;
+        }
+        println!("parsed 4M frames in {:?}", start.elapsed());
+        assert!(sum.is_finite());
+    }
+
+    #[test]
+    fn parse_value_with_decimals() {
+        assert_eq!(parse_value("3,10"), Ok((3.1, 2)));
+        assert_eq!(parse_value("3,1"), Ok((3.1, 1)));
+        assert_eq!(parse_value("   -0,000"), Ok((0.0, 3)));
+        assert_eq!(parse_value("1.5"), Ok((1.5, 1)));
+        assert_eq!(parse_value("7,"), Ok((7.0, 0)));
+        assert_eq!(parse_value("  "), Err(Error::EmptyValue));
+        assert!(matches!(parse_value("3,x"), Err(Error::BalanceValue(..))));
+        for value in ["NaN", "inf", "-Infinity", "1e40"] {
+            assert_eq!(parse_value(value), Err(Error::NonFiniteValue(value.into())));
+        }
+    }
+
+    #[test]
+    fn parse_message_with_unit() {
+        let protocol = Protocol::default();
+        let msg = protocol.parse_message("000101N        3,1 kg").unwrap();
+        assert_eq!(msg.value, 3.1);
+        assert_eq!(msg.unit, Unit::Kilogram);
+        let msg = protocol.parse_message("000101N       -250 g").unwrap();
+        assert_eq!(msg.value, -250.0);
+        assert_eq!(msg.unit, Unit::Gram);
+    }
+
+    #[test]
+    fn parse_non_finite_values() {
+        let protocol = Protocol::default();
+        for frame in [
+            "000101N        NaN kg",
+            "000101N       -inf kg",
+            "000101N   infinity",
+        ] {
+            assert!(matches!(
+                protocol.parse_message(frame),
+                Err(Error::NonFiniteValue(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn parse_message_without_unit() {
+        let protocol = Protocol {
+            unit: UnitField::Absent(Unit::Gram),
+            ..Protocol::default()
+        };
+        let msg = protocol.parse_message("000101N          250").unwrap();
+        assert_eq!(msg.value, 250.0);
+        assert_eq!(msg.unit, Unit::Gram);
+        let msg = protocol.parse_message("000101N 0123456,78").unwrap();
+        assert_eq!(msg.value, 123_456.78);
+        let msg = protocol.parse_message("000101N    -   3,7").unwrap();
+        assert_eq!(msg.value, -3.7);
+
+        // The last characters belong to the value
+        assert!(matches!(
+            protocol.parse_message("000101N        3,1 kg"),
+            Err(Error::BalanceValue(..))
+        ));
+        assert_eq!(
+            protocol.parse_message("000101N       "),
+            Err(Error::EmptyValue)
+        );
+    }
+
+    #[test]
+    fn parse_message_length_boundaries() {
+        let protocol = Protocol::default();
+        assert_eq!(
+            protocol.parse_message("000101"),
+            Err(Error::MessageLength("000101".into()))
+        );
+        // The shortest valid frames have an empty value region
+        assert_eq!(protocol.parse_message("000101N"), Err(Error::EmptyValue));
+        assert_eq!(protocol.parse_message("000101N "), Err(Error::EmptyValue));
+        assert_eq!(protocol.parse_message("000101Ng"), Err(Error::EmptyValue));
+        assert_eq!(protocol.parse_message("000101Nkg"), Err(Error::EmptyValue));
+        assert_eq!(protocol.parse_message("000101N1").unwrap().value, 1.0);
+
+        let longest = "000101N       123456,789 kg";
+        assert_eq!(longest.len(), MAX_MESSAGE_LEN);
+        assert_eq!(protocol.parse_message(longest).unwrap().value, 123_456.79);
+        assert!(matches!(
+            protocol.parse_message("000101N        123456,789 kg"),
+            Err(Error::MessageLength(_))
+        ));
+    }
+
+    #[test]
+    fn parse_id_first_frames() {
+        let protocol = Protocol {
+            order: FieldOrder::IdFirst,
+            ..Protocol::default()
+        };
+        assert_eq!(
+            protocol.parse_message("030010N   3,5 kg\r\n").unwrap(),
+            Message::new(3, 3.5).with_standstill()
+        );
+        assert!(matches!(
+            protocol.parse_message("001003N   3,5 kg"),
+            Err(Error::ParseBoolean(..))
+        ));
+        let partial = protocol.parse_partial("121000N   x");
+        assert_eq!(partial.id, Some(12));
+        assert!(partial.status.unwrap().under_load);
+        assert_eq!(partial.value, None);
+
+        let protocol = Protocol {
+            layout: FieldLayout::Delimited(';'),
+            ..protocol
+        };
+        assert_eq!(
+            protocol.parse_message("03;0010;3,5;g").unwrap(),
+            Message::new(3, 3.5).with_standstill().with_unit(Unit::Gram)
+        );
+        assert_eq!(protocol.parse_partial("03;0010").id, Some(3));
+    }
+
+    #[test]
+    fn parse_delimited_fields() {
+        let protocol = Protocol {
+            layout: FieldLayout::Delimited(';'),
+            ..Protocol::default()
+        };
+        assert_eq!(
+            protocol.parse_message("0010;03;-3,5;g\r\n").unwrap(),
+            Message::new(3, -3.5)
+                .with_standstill()
+                .with_unit(Unit::Gram)
+        );
+        assert_eq!(
+            protocol.parse_message("0000; 7; 1234,5 kg").unwrap(),
+            Message::new(7, 1234.5)
+        );
+        assert_eq!(
+            protocol.parse_message("0000;07;12,5;lb;x"),
+            Err(Error::MessageLength("0000;07;12,5;lb;x".into()))
+        );
+        assert_eq!(
+            protocol.parse_message("0000;07;12,5;t"),
+            Err(Error::Unit("t".into()))
+        );
+        assert!(matches!(
+            protocol.parse_message("0000;123;3,5"),
+            Err(Error::BalanceIdTooLong(id)) if id == "123"
+        ));
+        assert!(matches!(
+            protocol.parse_message("0000;01;123456789012345678901234567890"),
+            Err(Error::MessageLength(_))
+        ));
+        assert!(matches!(
+            protocol.parse_message("0000;0X;12,5"),
+            Err(Error::BalanceId(..))
+        ));
+        let partial = protocol.parse_partial("1000;04;x,5;kg");
+        assert_eq!(partial.status, Some(Status::from_str("1000").unwrap()));
+        assert_eq!(partial.id, Some(4));
+        assert_eq!(partial.value, None);
+
+        let protocol = Protocol {
+            layout: FieldLayout::Delimited('\t'),
+            address_field_len: 2,
+            ..Protocol::default()
+        };
+        let msg = protocol.parse_message("12\t0000\t01\t2,5").unwrap();
+        assert_eq!(msg.address, Some(12));
+        assert_eq!(msg.value, 2.5);
+    }
+
+    #[test]
+    fn parse_partial_message() {
+        let protocol = Protocol::default();
+        let partial = protocol.parse_partial("001003N       3,x kg");
+        assert_eq!(partial.status, Some(Status::from_str("0010").unwrap()));
+        assert_eq!(partial.id, Some(3));
+        assert_eq!(partial.value, None);
+
+        let partial = protocol.parse_partial("00XX03N       3,5 kg");
+        assert_eq!(partial.status, None);
+        assert_eq!(partial.id, Some(3));
+        assert_eq!(partial.value, Some(3.5));
+
+        let partial = protocol.parse_partial("000009");
+        assert_eq!(partial.id, Some(9));
+        assert_eq!(partial.value, None);
+
+        let msg = protocol.parse_message("100107N      -12,5 kg").unwrap();
+        let partial = protocol.parse_partial("100107N      -12,5 kg");
+        assert_eq!(partial.status, Some(msg.status));
+        assert_eq!(partial.id, Some(7));
+        assert_eq!(partial.value, Some(-12.5));
+
+        assert_eq!(
+            protocol.parse_partial("000�ۿ3,9 kg"),
+            PartialMessage::default()
+        );
+        assert_eq!(protocol.parse_partial(""), PartialMessage::default());
+
+        let protocol = Protocol {
+            standstill: Standstill::Symbol('*'),
+            ..Protocol::default()
+        };
+        let partial = protocol.parse_partial("0000XXN       3,1 kg*");
+        assert!(partial.status.unwrap().standstill);
+        assert_eq!(partial.id, None);
+        assert_eq!(partial.value, Some(3.1));
+    }
+
+    #[test]
+    fn parse_address() {
+        let protocol = Protocol {
+            address_field_len: 2,
+            ..Protocol::default()
+        };
+        let msg = protocol.parse_message("07100003N        3,1 kg").unwrap();
+        assert_eq!(msg.address, Some(7));
+        assert!(msg.status.under_load);
+        assert!(!msg.status.standstill);
+        assert_eq!(msg.id, 3);
+        assert_eq!(msg.value, 3.1);
+        let longest = "99000101N       123456,789 kg";
+        assert_eq!(protocol.parse_message(longest).unwrap().address, Some(99));
+        assert!(matches!(
+            protocol.parse_message("0X100003N        3,1 kg"),
+            Err(Error::Address(address, _)) if address == "0X"
+        ));
+        assert!(matches!(
+            protocol.parse_message("07000101"),
+            Err(Error::MessageLength(_))
+        ));
+        let partial = protocol.parse_partial("07100003N        3,x kg");
+        assert_eq!(partial.address, Some(7));
+        assert_eq!(partial.id, Some(3));
+
+        let msg = Protocol::default()
+            .parse_message("100003N        3,1 kg")
+            .unwrap();
+        assert_eq!(msg.address, None);
+    }
+
+    #[test]
+    fn parse_standstill_symbol() {
+        let protocol = Protocol {
+            standstill: Standstill::Symbol('*'),
+            ..Protocol::default()
+        };
+        let msg = protocol.parse_message("000001N       3,1 kg*").unwrap();
+        assert!(msg.status.standstill);
+        assert_eq!(msg.value, 3.1);
+        assert_eq!(msg.unit, Unit::Kilogram);
+
+        let msg = protocol.parse_message("000001N       3,1 kg").unwrap();
+        assert!(!msg.status.standstill);
+        assert_eq!(msg.value, 3.1);
+
+        // The status bit is ignored
+        let msg = protocol.parse_message("001001N       3,1 kg").unwrap();
+        assert!(!msg.status.standstill);
+
+        let protocol = Protocol {
+            unit: UnitField::Absent(Unit::Kilogram),
+            standstill: Standstill::Symbol('S'),
+            ..Protocol::default()
+        };
+        let msg = protocol.parse_message("000001N      -2,5 S").unwrap();
+        assert!(msg.status.standstill);
+        assert_eq!(msg.value, -2.5);
+
+        let msg = Protocol::default()
+            .parse_message("001001N       3,1 kg")
+            .unwrap();
+        assert!(msg.status.standstill);
+    }
+
+    #[test]
+    fn ignore_markers_in_value() {
+        let frame = "000101N      3,5W kg";
+        assert!(matches!(
+            Protocol::default().parse_message(frame),
+            Err(Error::BalanceValue(..))
+        ));
+        let protocol = Protocol {
+            ignored_value_bytes: vec![b' ', b'W', b'*'],
+            ..Protocol::default()
+        };
+        assert_eq!(protocol.parse_message(frame).unwrap().value, 3.5);
+        assert_eq!(
+            protocol.parse_message("000101N   * -3,5 kg").unwrap().value,
+            -3.5
+        );
+        let protocol = Protocol {
+            ignored_value_bytes: Vec::new(),
+            ..Protocol::default()
+        };
+        assert!(matches!(
+            protocol.parse_message("000101N        3,5 kg"),
+            Err(Error::BalanceValue(..))
+        ));
+        assert_eq!(protocol.parse_message("000101N3,5kg").unwrap().value, 3.5);
+    }
+
+    #[test]
+    fn parse_sign_in_separate_column() {
+        let protocol = Protocol {
+            sign: SignFormat::SeparateColumn(0),
+            ..Protocol::default()
+        };
+        assert_eq!(
+            protocol
+                .parse_message("000101N-       3,9 kg")
+                .unwrap()
+                .value,
+            -3.9
+        );
+        assert_eq!(
+            protocol
+                .parse_message("000101N+       3,9 kg")
+                .unwrap()
+                .value,
+            3.9
+        );
+        assert_eq!(
+            protocol
+                .parse_message("000101N        3,9 kg")
+                .unwrap()
+                .value,
+            3.9
+        );
+
+        let protocol = Protocol {
+            sign: SignFormat::SeparateColumn(2),
+            ..Protocol::default()
+        };
+        assert_eq!(
+            protocol.parse_message("000101N  -   250 g").unwrap().value,
+            -250.0
+        );
+        assert_eq!(
+            protocol.parse_message("000101N   1250 g").unwrap().value,
+            1250.0
+        );
+        assert!(matches!(
+            protocol.parse_message("000101N --   250 g"),
+            Err(Error::BalanceValue(..))
+        ));
+    }
+
+    #[test]
+    fn parse_trailing_sign() {
+        let protocol = Protocol {
+            sign: SignFormat::Trailing,
+            ..Protocol::default()
+        };
+        let msg = protocol.parse_message("000101N        3,9- kg").unwrap();
+        assert_eq!(msg.value, -3.9);
+        assert_eq!(msg.unit, Unit::Kilogram);
+        assert_eq!(
+            protocol.parse_message("000101N  3,9+ kg").unwrap().value,
+            3.9
+        );
+        assert_eq!(
+            protocol.parse_message("000101N   3,9 kg").unwrap().value,
+            3.9
+        );
+        assert_eq!(
+            protocol.parse_message("000101N     250-g").unwrap().value,
+            -250.0
+        );
+        assert_eq!(
+            protocol.parse_message("000101N   - kg"),
+            Err(Error::BalanceValue(
+                "N   - kg".into(),
+                "-".parse::<f32>().unwrap_err()
+            ))
+        );
+
+        let protocol = Protocol {
+            unit: UnitField::Absent(Unit::Gram),
+            ..protocol
+        };
+        assert_eq!(
+            protocol.parse_message("000101N    1250- ").unwrap().value,
+            -1250.0
+        );
+
+        assert!(Protocol::default()
+            .parse_message("000101N        3,9- kg")
+            .is_err());
+    }
+
+    #[test]
+    fn round_values() {
+        let round = |v, decimals| {
+            let mut buf = [0; ROUND_BUF_LEN];
+            round_half_even(v, decimals, &mut buf).map(str::to_owned)
+        };
+        assert_eq!(round("3.145", 2), Some("3.14".into()));
+        assert_eq!(round("3.135", 2), Some("3.14".into()));
+        assert_eq!(round("3.1451", 2), Some("3.15".into()));
+        assert_eq!(round("3.144", 2), Some("3.14".into()));
+        assert_eq!(round("3.146", 2), Some("3.15".into()));
+        assert_eq!(round("3.1", 2), Some("3.1".into()));
+        assert_eq!(round("3", 2), Some("3".into()));
+        assert_eq!(round("2.5", 0), Some("2".into()));
+        assert_eq!(round("3.5", 0), Some("4".into()));
+        assert_eq!(round("9.995", 2), Some("10.00".into()));
+        assert_eq!(round("-9.96", 1), Some("-10.0".into()));
+        assert_eq!(round("-0.05", 1), Some("-0.0".into()));
+        assert_eq!(round("-0.15", 1), Some("-0.2".into()));
+        assert_eq!(round("099.99", 1), Some("100.0".into()));
+        assert_eq!(round(".5", 0), Some("0".into()));
+        assert_eq!(round(".6", 0), Some("1".into()));
+        assert_eq!(round("-.5", 0), Some("-0".into()));
+        assert_eq!(round("-.96", 1), Some("-1.0".into()));
+        assert_eq!(round("+9.5", 0), Some("+10".into()));
+        assert_eq!(round("7.", 1), Some("7".into()));
+        assert_eq!(round("3.x5", 1), None);
+        assert_eq!(round("1-9.96", 1), None);
+        assert_eq!(round("-", 0), None);
+        assert_eq!(round("-.", 0), None);
+        assert_eq!(round("NaN", 0), None);
+
+        let protocol = Protocol {
+            round_to_decimals: Some(2),
+            ..Protocol::default()
+        };
+        let msg = protocol.parse_message("000001N    2,345 kg").unwrap();
+        assert_eq!(msg.value, 2.34);
+        let msg = protocol.parse_message("000001N   -2,355 kg").unwrap();
+        assert_eq!(msg.value, -2.36);
+        let msg = protocol.parse_message("000001N        3,1 kg").unwrap();
+        assert_eq!(msg.value, 3.1);
+        assert!(protocol.parse_message("000001N    3,1x5 kg").is_err());
+
+        let protocol = Protocol {
+            round_to_decimals: Some(0),
+            ..Protocol::default()
+        };
+        assert_eq!(
+            protocol
+                .parse_message("000101N        -,5 kg")
+                .unwrap()
+                .value,
+            0.0
+        );
+        assert_eq!(
+            protocol
+                .parse_message("000101N         ,5 kg")
+                .unwrap()
+                .value,
+            0.0
+        );
+        assert_eq!(
+            protocol
+                .parse_message("000101N        -,6 kg")
+                .unwrap()
+                .value,
+            -1.0
+        );
+        for frame in [
+            "000101N     1-9,96 kg",
+            "000101N         -- kg",
+            "000101N      1,2+ kg",
+        ] {
+            assert!(matches!(
+                protocol.parse_message(frame),
+                Err(Error::BalanceValue(..))
+            ));
+            assert!(matches!(
+                Protocol::default().parse_message(frame),
+                Err(Error::BalanceValue(..))
+            ));
+        }
+        assert!(matches!(
+            protocol.parse_message("000101N        NaN kg"),
+            Err(Error::NonFiniteValue(_))
+        ));
+        let msg = Protocol::default()
+            .parse_message("000001N    3,145 kg")
+            .unwrap();
+        assert_eq!(msg.value, 3.145);
+    }
+
+    #[test]
+    fn strip_framing_bytes() {
+        let protocol = Protocol {
+            framing_bytes: vec![0x02, 0x03],
+            ..Protocol::default()
+        };
+        let msg = Message::new(1, 3.5);
+        assert_eq!(
+            protocol
+                .parse_message("\x02000001N        3,5 kg\x03")
+                .unwrap(),
+            msg
+        );
+        assert_eq!(
+            protocol
+                .parse_message("\x02 000001N        3,5 kg \x03\r\n")
+                .unwrap(),
+            msg
+        );
+        assert_eq!(
+            protocol
+                .parse_response("\x00\x02000001N 3,5 kg\x03")
+                .unwrap(),
+            Response::Message(msg)
+        );
+        assert_eq!(
+            protocol.parse_response("\x02\x06\x03").unwrap(),
+            Response::Ack
+        );
+        assert_eq!(
+            protocol
+                .parse_partial("\x02000001N        3,5 kg\x03")
+                .value,
+            Some(3.5)
+        );
+        assert!(Protocol::default()
+            .parse_message("\x02000001N        3,5 kg\x03")
+            .is_err());
+    }
+
+    #[test]
+    fn parse_embedded_line_breaks() {
+        let frame = "000001N       3,1\r kg";
+        assert!(Protocol::default().parse_message(frame).is_err());
+
+        let protocol = Protocol {
+            strip_line_breaks: true,
+            ..Protocol::default()
+        };
+        let msg = protocol.parse_message(frame).unwrap();
+        assert_eq!(msg.value, 3.1);
+        assert_eq!(msg.unit, Unit::Kilogram);
+        let msg = protocol.parse_message("000001N       3,1 k\r\ng").unwrap();
+        assert_eq!(msg.unit, Unit::Kilogram);
+    }
+
+    #[test]
+    fn parse_remapped_control_bytes() {
+        let protocol = Protocol {
+            ack: b'Y',
+            nak: b'N',
+            ..Protocol::default()
+        };
+        assert_eq!(protocol.parse_response("Y").unwrap(), Response::Ack);
+        assert_eq!(protocol.parse_response("N\r\n").unwrap(), Response::Nak);
+        assert!(matches!(
+            protocol.parse_response("\u{6}"),
+            Err(Error::MessageLength(_))
+        ));
+        assert_eq!(
+            protocol.parse_response("000009N          3,5 kg").unwrap(),
+            Response::Message(Message::new(9, 3.5))
+        );
+    }
+
+    #[test]
+    fn non_ascii_chars_are_no_control_bytes() {
+        let protocol = Protocol::default();
+        // The low byte of U+0100 is NUL, U+0106 ends with ACK
+        assert!(matches!(
+            protocol.parse_response("\u{100}000001N  1,5 kg"),
+            Err(Error::NonAsciiStr(_))
+        ));
+        assert!(protocol.parse_response("\u{106}").is_err());
+        assert!(protocol.parse_response("\u{115}").is_err());
+    }
+
+    #[test]
+    fn parse_status_only_frames() {
+        let status = Status::from_str("0100").unwrap();
+        assert_eq!(
+            Protocol::default().parse_response("010003N        "),
+            Err(Error::EmptyValue)
+        );
+        let protocol = Protocol {
+            status_only_frames: true,
+            ..Protocol::default()
+        };
+        assert_eq!(
+            protocol.parse_response("010003N           kg"),
+            Ok(Response::StatusOnly { id: 3, status })
+        );
+        assert_eq!(
+            protocol.parse_response("010003N"),
+            Ok(Response::StatusOnly { id: 3, status })
+        );
+        assert_eq!(
+            protocol.parse_response("010003"),
+            Ok(Response::StatusOnly { id: 3, status })
+        );
+        assert_eq!(
+            protocol.parse_response("000003N   2,5 kg"),
+            Ok(Response::Message(Message::new(3, 2.5)))
+        );
+        assert_eq!(protocol.parse_response("0100XXN"), Err(Error::EmptyValue));
+        assert!(matches!(
+            protocol.parse_response("0100"),
+            Err(Error::MessageLength(_))
+        ));
+        assert!(matches!(
+            protocol.parse_response("010003N   2,x kg"),
+            Err(Error::BalanceValue(..))
+        ));
+    }
+
+    #[test]
+    fn parse_response() {
+        let protocol = Protocol {
+            unit: UnitField::Absent(Unit::Kilogram),
+            ..Protocol::default()
+        };
+        assert_eq!(
+            protocol.parse_response("000009N          3,5\r\n").unwrap(),
+            Response::Message(Message::new(9, 3.5))
+        );
+        assert_eq!(protocol.parse_response("\u{6}").unwrap(), Response::Ack);
+        assert_eq!(protocol.parse_response("\u{15}").unwrap(), Response::Nak);
+        assert_eq!(protocol.parse_response("\0\u{6}").unwrap(), Response::Ack);
+        assert_eq!(
+            protocol.parse_response("\0 \0\u{15}\r\n").unwrap(),
+            Response::Nak
+        );
+        assert_eq!(
+            protocol.parse_response("\x00000009N          3,5").unwrap(),
+            Response::Message(Message::new(9, 3.5))
+        );
+        assert!(protocol.parse_response("\0\0").is_err());
+        assert!(protocol.parse_response("<T>").is_err());
+
+        let protocol = Protocol {
+            echo: true,
+            ..protocol
+        };
+        assert_eq!(
+            protocol.parse_response("<T>").unwrap(),
+            Response::Echo(Echo::Command(Command::Tare))
+        );
+        assert_eq!(
+            protocol.parse_response("<tC>\r\n").unwrap(),
+            Response::Echo(Echo::Command(Command::ClearTare))
+        );
+        assert_eq!(
+            protocol.parse_response("<A>").unwrap(),
+            Response::Echo(Echo::Query(Query::Once))
+        );
+        assert_eq!(
+            protocol.parse_response("<b>").unwrap(),
+            Response::Echo(Echo::Query(Query::OnceOnChange))
+        );
+        assert_eq!(protocol.parse_response("\u{6}").unwrap(), Response::Ack);
+        assert!(matches!(
+            protocol.parse_response("<X>"),
+            Err(Error::UnknownFrame(_))
+        ));
+    }
+}
@@ -0,0 +1,453 @@
+//! Reading messages from a byte stream that is shared by several balances.
+
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    io,
+    io::Read,
+    str,
+};
+
+use crate::{protocol::is_padding, Error, Message, Protocol, Response, Result};
+
+/// Collects received bytes and splits them into frames.
+#[derive(Debug, Default)]
+pub(crate) struct FrameBuffer {
+    buf: Vec<u8>,
+}
+
+impl FrameBuffer {
+    pub(crate) const fn new() -> Self {
+        Self { buf: Vec::new() }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.buf.len()
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.buf.clear();
+    }
+
+    pub(crate) fn extend(&mut self, bytes: &[u8]) {
+        self.buf.extend_from_slice(bytes);
+    }
+
+    /// Reads from `reader` until a complete frame is available.
+    pub(crate) fn read_frame(
+        &mut self,
+        reader: &mut impl Read,
+        protocol: &Protocol,
+    ) -> Result<Vec<u8>> {
+        loop {
+            if let Some(frame) = self.next_frame(protocol) {
+                return frame;
+            }
+            // Large enough for a burst of frames, see `MultiBalanceReader::latest_only`
+            let mut chunk = [0; 512];
+            let n = reader.read(&mut chunk)?;
+            if n == 0 {
+                return Err(Error::Io(io::ErrorKind::UnexpectedEof));
+            }
+            self.buf.extend_from_slice(&chunk[..n]);
+        }
+    }
+
+    /// Takes the bytes of the next complete frame out of the buffer.
+    ///
+    /// If there is no frame end within [`Protocol::max_frame_len`] bytes,
+    /// the buffer is discarded and [`Error::FrameTooLong`] is returned.
+    pub(crate) fn next_frame(&mut self, protocol: &Protocol) -> Option<Result<Vec<u8>>> {
+        let frame = self.complete_frame(protocol);
+        if frame.is_none() && self.buf.len() > protocol.max_frame_len {
+            let len = self.buf.len();
+            self.buf.clear();
+            return Some(Err(Error::FrameTooLong(len)));
+        }
+        frame.map(Ok)
+    }
+
+    fn complete_frame(&mut self, protocol: &Protocol) -> Option<Vec<u8>> {
+        let lf_only = protocol.strip_line_breaks;
+        loop {
+            let start = self.buf.iter().position(|b| !is_padding(*b))?;
+            let b = self.buf[start];
+            if b == protocol.ack || b == protocol.nak || Some(b) == protocol.busy {
+                return Some(self.buf.drain(..=start).collect());
+            }
+            let is_line_break = |b: u8| b == b'\n' || (b == b'\r' && !lf_only);
+            let is_framing = |b: &u8| protocol.framing_bytes.contains(b);
+            // An echoed request might not be followed by a line break
+            let end = if b == b'<' && protocol.echo {
+                self.buf
+                    .iter()
+                    .position(|b| *b == b'>' || is_line_break(*b))?
+            } else {
+                // The closing framing byte follows the content,
+                // the opening one precedes it.
+                let mut content = false;
+                let end = self.buf[start..].iter().position(|b| {
+                    let end = is_line_break(*b) || (content && is_framing(b));
+                    content |= !is_framing(b) && !is_padding(*b);
+                    end
+                })?;
+                start + end
+            };
+            let frame: Vec<u8> = self.buf.drain(..=end).collect();
+            if frame.iter().all(|b| is_padding(*b) || is_framing(b)) {
+                continue;
+            }
+            return Some(frame);
+        }
+    }
+}
+
+/// Parses the bytes of a single frame.
+///
+/// Frames with non-ASCII bytes, i.e. corrupted ones, are rejected as a whole;
+/// the frame buffer continues with the next frame.
+/// Only a single ACK, NAK or busy byte may be any byte.
+pub(crate) fn parse_frame(protocol: &Protocol, frame: &[u8]) -> Result<Response> {
+    let start = frame
+        .iter()
+        .position(|b| !is_padding(*b))
+        .unwrap_or(frame.len());
+    if let [b] = frame[start..] {
+        if b == protocol.ack || b == protocol.nak || Some(b) == protocol.busy {
+            return protocol.parse_response(&char::from(b).to_string());
+        }
+    }
+    match str::from_utf8(frame) {
+        Ok(frame) if frame.is_ascii() => protocol.parse_response(frame),
+        _ => Err(Error::NonAsciiStr(
+            String::from_utf8_lossy(frame).trim().to_owned(),
+        )),
+    }
+}
+
+/// Reads the messages of several balances from one stream,
+/// e.g. a shared RS485 line, and yields them together with their balance ID.
+///
+/// ACK and NAK frames and messages of balances that are not
+/// subscribed are skipped. The iterator ends with the stream.
+///
+/// ```
+/// # use soehnle_t3005::MultiBalanceReader;
+/// let bus: &[u8] = b"000101N  1,5 kg\r\n000102N  2,5 kg\r\n000103N  3,5 kg\r\n";
+/// let reader = MultiBalanceReader::new(bus).subscribe([1, 3]);
+/// let ids: Vec<u8> = reader.map(|msg| msg.unwrap().0).collect();
+/// assert_eq!(ids, [1, 3]);
+/// ```
+#[derive(Debug)]
+pub struct MultiBalanceReader<R> {
+    reader: R,
+    protocol: Protocol,
+    ids: Option<HashSet<u8>>,
+    buf: FrameBuffer,
+    latest_only: bool,
+    pending: VecDeque<Result<(u8, Message)>>,
+}
+
+impl<R: Read> MultiBalanceReader<R> {
+    /// Creates a reader that yields the messages of all balances.
+    pub fn new(reader: R) -> Self {
+        Self::with_protocol(reader, Protocol::default())
+    }
+
+    pub const fn with_protocol(reader: R, protocol: Protocol) -> Self {
+        Self {
+            reader,
+            protocol,
+            ids: None,
+            buf: FrameBuffer::new(),
+            latest_only: false,
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// Only yields the messages of the given balances.
+    #[must_use]
+    pub fn subscribe(mut self, ids: impl IntoIterator<Item = u8>) -> Self {
+        self.ids = Some(ids.into_iter().collect());
+        self
+    }
+
+    /// Only yields the newest message of each balance among the frames
+    /// that have been received together, e.g. for a live display
+    /// that doesn't need every intermediate value of a fast load.
+    ///
+    /// The older messages are discarded on purpose, errors are still yielded.
+    ///
+    /// ```
+    /// # use soehnle_t3005::MultiBalanceReader;
+    /// let burst: &[u8] = b"000001N 1,5 kg\r\n000001N 2,5 kg\r\n000001N 3,5 kg\r\n";
+    /// let mut reader = MultiBalanceReader::new(burst).latest_only();
+    /// assert_eq!(reader.next().unwrap().unwrap().1.value, 3.5);
+    /// assert!(reader.next().is_none());
+    /// ```
+    #[must_use]
+    pub const fn latest_only(mut self) -> Self {
+        self.latest_only = true;
+        self
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    fn is_subscribed(&self, id: u8) -> bool {
+        self.ids.as_ref().map_or(true, |ids| ids.contains(&id))
+    }
+
+    fn push(&mut self, frame: &[u8]) {
+        match parse_frame(&self.protocol, frame) {
+            Ok(Response::Message(msg)) if self.is_subscribed(msg.id) => {
+                self.pending.push_back(Ok((msg.id, msg)));
+            }
+            Ok(_) => {}
+            Err(err) => self.pending.push_back(Err(err)),
+        }
+    }
+
+    /// Drops all but the last pending message of each balance.
+    fn discard_superseded(&mut self) {
+        let mut newer = HashSet::new();
+        let mut latest: Vec<_> = self
+            .pending
+            .drain(..)
+            .rev()
+            .filter(|item| item.as_ref().map_or(true, |(id, _)| newer.insert(*id)))
+            .collect();
+        latest.reverse();
+        self.pending = latest.into();
+    }
+}
+
+impl<R: Read> Iterator for MultiBalanceReader<R> {
+    type Item = Result<(u8, Message)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(item) = self.pending.pop_front() {
+                return Some(item);
+            }
+            let frame = match self.buf.read_frame(&mut self.reader, &self.protocol) {
+                Ok(frame) => frame,
+                Err(Error::Io(io::ErrorKind::UnexpectedEof)) => return None,
+                Err(err) => return Some(Err(err)),
+            };
+            self.push(&frame);
+            if self.latest_only {
+                while let Some(frame) = self.buf.next_frame(&self.protocol) {
+                    match frame {
+                        Ok(frame) => self.push(&frame),
+                        Err(err) => self.pending.push_back(Err(err)),
+                    }
+                }
+                self.discard_superseded();
+            }
+        }
+    }
+}
+
+/// Suppresses messages that repeat the previous reading of the same balance
+/// (see [`Message::same_reading`]), e.g. frames resent by the terminal
+/// in [`Query::OnceOnChange`](crate::Query::OnceOnChange) mode.
+///
+/// Errors are passed through.
+///
+/// ```
+/// # use soehnle_t3005::{ChangeFilter, MultiBalanceReader};
+/// let stream: &[u8] = b"000001N 1,5 kg\r\n001001N 1,5 kg\r\n000001N 1,7 kg\r\n";
+/// let messages = MultiBalanceReader::new(stream).map(|msg| msg.map(|(_, msg)| msg));
+/// let values: Vec<f32> = ChangeFilter::new(messages).map(|msg| msg.unwrap().value).collect();
+/// assert_eq!(values, [1.5, 1.7]);
+/// ```
+#[derive(Debug)]
+pub struct ChangeFilter<I> {
+    messages: I,
+    last: HashMap<u8, Message>,
+}
+
+impl<I> ChangeFilter<I>
+where
+    I: Iterator<Item = Result<Message>>,
+{
+    pub fn new(messages: impl IntoIterator<IntoIter = I>) -> Self {
+        Self {
+            messages: messages.into_iter(),
+            last: HashMap::new(),
+        }
+    }
+}
+
+impl<I> Iterator for ChangeFilter<I>
+where
+    I: Iterator<Item = Result<Message>>,
+{
+    type Item = Result<Message>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let msg = match self.messages.next()? {
+                Ok(msg) => msg,
+                Err(err) => return Some(Err(err)),
+            };
+            if self
+                .last
+                .insert(msg.id, msg)
+                .map_or(true, |last| !last.same_reading(&msg))
+            {
+                return Some(Ok(msg));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::float_cmp)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn route_messages_by_id() {
+        let bus: &[u8] =
+            b"000001N  1,5 kg\r\n\x06000002N  2,5 kg\r\n0000XXN  0,0 kg\r\n000001N  1,7 kg\r\n";
+        let messages: Vec<_> = MultiBalanceReader::new(bus).collect();
+        assert_eq!(messages.len(), 4);
+        assert_eq!(messages[0], Ok((1, Message::new(1, 1.5))));
+        assert_eq!(messages[1], Ok((2, Message::new(2, 2.5))));
+        assert!(matches!(messages[2], Err(Error::BalanceId(..))));
+        assert_eq!(messages[3], Ok((1, Message::new(1, 1.7))));
+    }
+
+    #[test]
+    fn resync_after_non_ascii_bytes() {
+        let bus: &[u8] = b"000001N  1,5 kg\r\n0000\xC302N  2,5 kg\r\n\xFF\r\n000103N  3,5\xC3\xA4kg\r\n000004N  4,5 kg\r\n";
+        let messages: Vec<_> = MultiBalanceReader::new(bus).collect();
+        assert_eq!(messages.len(), 5);
+        assert_eq!(messages[0], Ok((1, Message::new(1, 1.5))));
+        assert_eq!(
+            messages[1],
+            Err(Error::NonAsciiStr("0000\u{FFFD}02N  2,5 kg".into()))
+        );
+        assert!(matches!(messages[2], Err(Error::NonAsciiStr(_))));
+        assert_eq!(
+            messages[3],
+            Err(Error::NonAsciiStr("000103N  3,5äkg".into()))
+        );
+        assert_eq!(messages[4], Ok((4, Message::new(4, 4.5))));
+    }
+
+    #[test]
+    fn resync_after_endless_frame() {
+        let protocol = Protocol {
+            max_frame_len: 100,
+            ..Protocol::default()
+        };
+        let mut stream = vec![b'0'; 1000];
+        stream.extend_from_slice(b"\r\n000001N  1,5 kg\r\n");
+        let mut reader = MultiBalanceReader::with_protocol(stream.as_slice(), protocol);
+        // The rest of the garbage, read with the next chunk, has a frame end
+        assert_eq!(reader.next(), Some(Err(Error::FrameTooLong(512))));
+        assert!(matches!(reader.next(), Some(Err(Error::MessageLength(_)))));
+        assert_eq!(reader.next(), Some(Ok((1, Message::new(1, 1.5)))));
+        assert_eq!(reader.next(), None);
+
+        let endless = io::repeat(b'0');
+        let mut reader = MultiBalanceReader::new(endless);
+        for _ in 0..3 {
+            assert!(matches!(
+                reader.next(),
+                Some(Err(Error::FrameTooLong(len))) if len > 1024 && len <= 1024 + 512
+            ));
+        }
+    }
+
+    #[test]
+    fn split_framed_messages() {
+        let protocol = Protocol {
+            framing_bytes: vec![0x02, 0x03],
+            ..Protocol::default()
+        };
+        let bus: &[u8] = b"\x02000001N  1,5 kg\x03\x02000002N  2,5 kg\x03\r\n\x02\x06\x03000003N  3,5 kg\x03\x02000004N  4,5 kg\r\n\x03";
+        let ids: Vec<u8> = MultiBalanceReader::with_protocol(bus, protocol)
+            .map(|msg| msg.unwrap().0)
+            .collect();
+        assert_eq!(ids, [1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn stray_angle_bracket() {
+        let bus: &[u8] = b"<000001N  1,5 kg\r\n000002N  2,5 kg\r\n";
+        let messages: Vec<_> = MultiBalanceReader::new(bus).collect();
+        assert_eq!(messages.len(), 2);
+        assert!(messages[0].is_err());
+        assert_eq!(messages[1], Ok((2, Message::new(2, 2.5))));
+
+        // A broken echo ends at the line break
+        let protocol = Protocol {
+            echo: true,
+            ..Protocol::default()
+        };
+        let bus: &[u8] = b"<A>000001N  1,5 kg\r\n<t\r\n<tC>000002N  2,5 kg\r\n";
+        let messages: Vec<_> = MultiBalanceReader::with_protocol(bus, protocol).collect();
+        assert_eq!(messages.len(), 3);
+        assert_eq!(messages[0], Ok((1, Message::new(1, 1.5))));
+        assert!(matches!(messages[1], Err(Error::UnknownFrame(_))));
+        assert_eq!(messages[2], Ok((2, Message::new(2, 2.5))));
+    }
+
+    #[test]
+    fn skip_unsubscribed_ids() {
+        let bus: &[u8] = b"000001N  1,5 kg\r\n000002N  2,5 kg\r\n000007N  7,5 kg\r\n";
+        let mut reader = MultiBalanceReader::new(bus).subscribe([2]);
+        assert_eq!(reader.next(), Some(Ok((2, Message::new(2, 2.5)))));
+        assert_eq!(reader.next(), None);
+
+        let reader = MultiBalanceReader::new(bus).subscribe([]);
+        assert_eq!(reader.count(), 0);
+    }
+
+    #[test]
+    fn coalesce_burst_into_latest() {
+        let burst: &[u8] = b"000001N  1,5 kg\r\n000002N  2,5 kg\r\n000001N  1,6 kg\r\n0000XXN  0,0 kg\r\n000001N  1,7 kg\r\n000002N  2,6 kg\r\n000001N  1,8 kg\r\n";
+        let messages: Vec<_> = MultiBalanceReader::new(burst).latest_only().collect();
+        assert_eq!(messages.len(), 3);
+        assert!(matches!(messages[0], Err(Error::BalanceId(..))));
+        assert_eq!(messages[1], Ok((2, Message::new(2, 2.6))));
+        assert_eq!(messages[2], Ok((1, Message::new(1, 1.8))));
+
+        let messages: Vec<_> = MultiBalanceReader::new(burst)
+            .subscribe([2])
+            .latest_only()
+            .collect();
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[1], Ok((2, Message::new(2, 2.6))));
+    }
+
+    #[test]
+    fn suppress_repeated_readings() {
+        let messages = [
+            Ok(Message::new(1, 1.5)),
+            Ok(Message::new(1, 1.5).with_standstill()),
+            Ok(Message::new(2, 1.5)),
+            Err(Error::EmptyValue),
+            Ok(Message::new(1, 1.5)),
+            Ok(Message::new(2, 2.0)),
+            Ok(Message::new(1, 1.7)),
+            Ok(Message::new(1, 1.5)),
+        ];
+        let filtered: Vec<_> = ChangeFilter::new(messages).collect();
+        assert_eq!(
+            filtered,
+            [
+                Ok(Message::new(1, 1.5)),
+                Ok(Message::new(2, 1.5)),
+                Err(Error::EmptyValue),
+                Ok(Message::new(2, 2.0)),
+                Ok(Message::new(1, 1.7)),
+                Ok(Message::new(1, 1.5)),
+            ]
+        );
+    }
+}
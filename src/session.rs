@@ -0,0 +1,265 @@
+//! The request/response logic of the protocol without any IO.
+
+use std::collections::VecDeque;
+
+use crate::{
+    reader::FrameBuffer, terminal::parse_response, Command, DeviceProfile, Message, Protocol,
+    Query, Response, Result, Terminator, ToAsciiString,
+};
+
+/// A request that waits for its response.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Pending {
+    /// A command sent with ACK.
+    Command(Command),
+    Query(Query),
+}
+
+/// Something that happened in a [`Session`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Event {
+    /// The terminal acknowledged the command.
+    Ack(Command),
+    /// The terminal rejected the request.
+    Nak(Pending),
+    /// The response to a query.
+    Reply(Query, Message),
+    /// A response that no request waits for,
+    /// e.g. a message of a terminal that transmits continuously.
+    Unsolicited(Response),
+}
+
+/// Keeps track of the requests that wait for a response
+/// and matches the received frames with them.
+///
+/// A session does no IO, so that every transport (blocking, async, embedded)
+/// can share it: the caller writes the returned bytes of each request
+/// and [feeds](Session::feed) all received bytes into the session.
+///
+/// ```
+/// # use soehnle_t3005::{Command, Event, Session};
+/// let mut session = Session::default();
+/// let bytes = session.send_with_ack(Command::Tare)?;
+/// assert_eq!(bytes, b"<t>");
+/// session.feed(&[0x06]);
+/// assert_eq!(session.poll_event(), Some(Ok(Event::Ack(Command::Tare))));
+/// # Ok::<(), soehnle_t3005::Error>(())
+/// ```
+#[derive(Debug, Default)]
+pub struct Session {
+    protocol: Protocol,
+    profile: DeviceProfile,
+    terminator: Terminator,
+    buf: FrameBuffer,
+    pending: VecDeque<Pending>,
+}
+
+impl Session {
+    /// Creates a session with the given frame format,
+    /// `terminator` is appended to every request.
+    #[must_use]
+    pub const fn new(protocol: Protocol, profile: DeviceProfile, terminator: Terminator) -> Self {
+        Self {
+            protocol,
+            profile,
+            terminator,
+            buf: FrameBuffer::new(),
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// Returns the bytes of a command that isn't answered.
+    pub fn send(&mut self, cmd: Command) -> Result<Vec<u8>> {
+        self.encode(&cmd)
+    }
+
+    /// Returns the bytes of a command and waits for the ACK.
+    pub fn send_with_ack(&mut self, cmd: Command) -> Result<Vec<u8>> {
+        let bytes = self.encode(&cmd.with_ack())?;
+        self.pending.push_back(Pending::Command(cmd));
+        Ok(bytes)
+    }
+
+    /// Returns the bytes of a query and waits for the message.
+    pub fn query(&mut self, query: Query) -> Result<Vec<u8>> {
+        let bytes = self.encode(&query)?;
+        self.pending.push_back(Pending::Query(query));
+        Ok(bytes)
+    }
+
+    /// Adds received bytes, they don't need to be complete frames.
+    pub fn feed(&mut self, bytes: &[u8]) {
+        self.buf.extend(bytes);
+    }
+
+    /// Returns the next event of the received frames,
+    /// or `None` if more bytes are needed.
+    ///
+    /// Responses are matched with the requests in the order they were sent.
+    /// A frame that can't be parsed is reported as an error and is taken
+    /// as the (garbled) response to the oldest pending request.
+    /// Echoed requests are skipped.
+    pub fn poll_event(&mut self) -> Option<Result<Event>> {
+        loop {
+            let frame = match self.buf.next_frame(&self.protocol)? {
+                Ok(frame) => frame,
+                Err(err) => return Some(Err(err)),
+            };
+            let response = match parse_response(&self.protocol, &self.profile, &frame) {
+                Ok(response) => response,
+                Err(err) => {
+                    self.pending.pop_front();
+                    return Some(Err(err));
+                }
+            };
+            let event = match (self.pending.front().copied(), response) {
+                (_, Response::Echo(_)) => continue,
+                (Some(Pending::Command(cmd)), Response::Ack) => Event::Ack(cmd),
+                (Some(pending), Response::Nak) => Event::Nak(pending),
+                (Some(Pending::Query(query)), Response::Message(msg)) => Event::Reply(query, msg),
+                (_, response) => return Some(Ok(Event::Unsolicited(response))),
+            };
+            self.pending.pop_front();
+            return Some(Ok(event));
+        }
+    }
+
+    /// The requests that wait for a response, the oldest first.
+    pub fn pending(&self) -> impl Iterator<Item = &Pending> {
+        self.pending.iter()
+    }
+
+    /// Stops waiting for the response to the oldest request,
+    /// e.g. after the transport timed out.
+    pub fn abandon(&mut self) -> Option<Pending> {
+        self.pending.pop_front()
+    }
+
+    /// Discards all pending requests and received bytes, e.g. after reconnecting.
+    pub fn reset(&mut self) {
+        self.pending.clear();
+        self.buf.clear();
+    }
+
+    fn encode(&self, request: &impl ToAsciiString) -> Result<Vec<u8>> {
+        let mut bytes = request.to_ascii_string_with(&self.profile)?.into_bytes();
+        bytes.extend_from_slice(self.terminator.as_bytes());
+        Ok(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Error, TareValue, ACK, NAK};
+
+    #[test]
+    fn tare_handshake() {
+        let mut session = Session::new(
+            Protocol::default(),
+            DeviceProfile::default(),
+            Terminator::CrLf,
+        );
+        let cmd = Command::SetTare(TareValue::new(1500).unwrap());
+        assert_eq!(session.send_with_ack(cmd).unwrap(), b"<t0001500>\r\n");
+        assert_eq!(session.send(Command::Tare).unwrap(), b"<T>\r\n");
+        assert_eq!(
+            session.pending().collect::<Vec<_>>(),
+            [&Pending::Command(cmd)]
+        );
+        assert_eq!(session.poll_event(), None);
+        session.feed(&[ACK]);
+        assert_eq!(session.poll_event(), Some(Ok(Event::Ack(cmd))));
+        assert_eq!(session.pending().count(), 0);
+
+        session.send_with_ack(Command::ClearTare).unwrap();
+        session.feed(&[NAK]);
+        assert_eq!(
+            session.poll_event(),
+            Some(Ok(Event::Nak(Pending::Command(Command::ClearTare))))
+        );
+        assert_eq!(session.poll_event(), None);
+    }
+
+    #[test]
+    fn query_sequence() {
+        let mut session = Session::default();
+        assert_eq!(session.query(Query::Once).unwrap(), b"<A>");
+        session.send_with_ack(Command::Tare).unwrap();
+        session.query(Query::OnceOnChange).unwrap();
+
+        session.feed(b"000001N      ");
+        assert_eq!(session.poll_event(), None);
+        session.feed(b"  1,5 kg\r\n\x06000001N        0,0 kg\r\n");
+        assert_eq!(
+            session.poll_event(),
+            Some(Ok(Event::Reply(Query::Once, Message::new(1, 1.5))))
+        );
+        assert_eq!(session.poll_event(), Some(Ok(Event::Ack(Command::Tare))));
+        assert_eq!(
+            session.poll_event(),
+            Some(Ok(Event::Reply(Query::OnceOnChange, Message::new(1, 0.0))))
+        );
+
+        session.feed(b"000001N        0,5 kg\r\n\x06");
+        assert_eq!(
+            session.poll_event(),
+            Some(Ok(Event::Unsolicited(Response::Message(Message::new(
+                1, 0.5
+            )))))
+        );
+        assert_eq!(
+            session.poll_event(),
+            Some(Ok(Event::Unsolicited(Response::Ack)))
+        );
+    }
+
+    #[test]
+    fn skip_echoed_requests() {
+        let protocol = Protocol {
+            echo: true,
+            ..Protocol::default()
+        };
+        let mut session = Session::new(protocol, DeviceProfile::default(), Terminator::None);
+        session.query(Query::Once).unwrap();
+        session.send_with_ack(Command::Tare).unwrap();
+        session.feed(b"<A>000001N  1,5 kg\r\n<t>\x06");
+        assert_eq!(
+            session.poll_event(),
+            Some(Ok(Event::Reply(Query::Once, Message::new(1, 1.5))))
+        );
+        assert_eq!(session.poll_event(), Some(Ok(Event::Ack(Command::Tare))));
+        assert_eq!(session.pending().count(), 0);
+    }
+
+    #[test]
+    fn resync_after_garbled_response() {
+        let mut session = Session::default();
+        session.query(Query::Once).unwrap();
+        session.query(Query::Once).unwrap();
+        session.feed(b"000001?        1,5 kg\r\n000001N        2,5 kg\r\n");
+        assert_eq!(
+            session.poll_event(),
+            Some(Err(Error::WeightType("?".into())))
+        );
+        assert_eq!(
+            session.poll_event(),
+            Some(Ok(Event::Reply(Query::Once, Message::new(1, 2.5))))
+        );
+
+        session.query(Query::Once).unwrap();
+        assert_eq!(session.abandon(), Some(Pending::Query(Query::Once)));
+        assert_eq!(session.abandon(), None);
+
+        session.query(Query::Once).unwrap();
+        session.feed(b"000001N  ");
+        session.reset();
+        session.feed(b"000001N        2,5 kg\r\n");
+        assert_eq!(
+            session.poll_event(),
+            Some(Ok(Event::Unsolicited(Response::Message(Message::new(
+                1, 2.5
+            )))))
+        );
+    }
+}
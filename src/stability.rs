@@ -0,0 +1,113 @@
+//! Host-side detection of a settled weight.
+
+use std::collections::VecDeque;
+
+use crate::{Message, ValueState, Weight};
+
+/// Decides whether the weight has settled by comparing consecutive readings,
+/// independent of the standstill bit of the terminal.
+///
+/// The weight is stable once the last `required_samples` values differ
+/// by no more than `tolerance`. Overload and underload readings start over.
+///
+/// ```
+/// # use soehnle_t3005::{Message, StabilityDetector, Weight};
+/// let mut detector = StabilityDetector::new(Weight::grams(10.0), 3);
+/// assert!(!detector.push(&Message::new(1, 1.2)));
+/// assert!(!detector.push(&Message::new(1, 1.002)));
+/// assert!(!detector.push(&Message::new(1, 1.0)));
+/// assert!(detector.push(&Message::new(1, 1.005)));
+/// ```
+#[derive(Debug, Clone)]
+pub struct StabilityDetector {
+    /// The largest difference between the compared readings.
+    pub tolerance: Weight,
+    /// Number of consecutive readings that must lie within the tolerance,
+    /// at least one.
+    pub required_samples: usize,
+    values: VecDeque<f32>,
+}
+
+impl StabilityDetector {
+    #[must_use]
+    pub const fn new(tolerance: Weight, required_samples: usize) -> Self {
+        Self {
+            tolerance,
+            required_samples,
+            values: VecDeque::new(),
+        }
+    }
+
+    /// Adds a reading and returns whether the weight is stable.
+    pub fn push(&mut self, msg: &Message) -> bool {
+        let ValueState::Weight(_) = msg.value_state() else {
+            self.reset();
+            return false;
+        };
+        self.values
+            .push_back(msg.weight().to_unit(self.tolerance.unit).value);
+        while self.values.len() > self.required_samples.max(1) {
+            self.values.pop_front();
+        }
+        self.is_stable()
+    }
+
+    /// Whether the last `required_samples` readings lie within the tolerance.
+    #[must_use]
+    pub fn is_stable(&self) -> bool {
+        if self.values.is_empty() || self.values.len() < self.required_samples {
+            return false;
+        }
+        let (min, max) = self
+            .values
+            .iter()
+            .fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), v| {
+                (min.min(*v), max.max(*v))
+            });
+        max - min <= self.tolerance.value
+    }
+
+    /// Forgets all readings, e.g. after taring.
+    pub fn reset(&mut self) {
+        self.values.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Unit;
+
+    #[test]
+    fn settling_sequence() {
+        let mut detector = StabilityDetector::new(Weight::grams(5.0), 3);
+        let readings = [0.0, 2.4, 2.55, 2.502, 2.498, 2.501, 2.52, 2.519, 2.518];
+        let stable: Vec<bool> = readings
+            .into_iter()
+            .map(|kg| detector.push(&Message::new(1, kg)))
+            .collect();
+        assert_eq!(
+            stable,
+            [false, false, false, false, false, true, false, false, true]
+        );
+        detector.reset();
+        assert!(!detector.is_stable());
+    }
+
+    #[test]
+    fn start_over_after_overload() {
+        let mut detector = StabilityDetector::new(Weight::kilograms(0.01), 2);
+        assert!(!detector.push(&Message::new(1, 5.0)));
+        assert!(detector.push(&Message::new(1, 5.0)));
+        assert!(!detector.push(&Message::new(1, 5.0).with_over_load()));
+        assert!(!detector.push(&Message::new(1, 5.0)));
+        assert!(detector.push(&Message::new(1, 5000.0).with_unit(Unit::Gram)));
+    }
+
+    #[test]
+    fn no_samples_required() {
+        let mut detector = StabilityDetector::new(Weight::zero(), 0);
+        assert!(!detector.is_stable());
+        assert!(detector.push(&Message::new(1, 1.0)));
+    }
+}
@@ -0,0 +1,1427 @@
+//! Blocking communication with a terminal over any byte stream.
+
+use std::{
+    io::{self, Read, Write},
+    ops::ControlFlow,
+    str,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    clock::{Clock, SystemClock},
+    protocol::is_padding,
+    reader::{parse_frame, FrameBuffer},
+    Command, CommandSequence, DeviceProfile, Echo, Error, Message, Protocol, Query, Response,
+    Result, TareValue, Terminator, ToAsciiString, ValueState, Weight, WithAck,
+};
+
+/// Encodes `cmd` and writes it, followed by the `terminator`, to `w`.
+///
+/// This works with any writer, e.g. a serial port or a `Vec<u8>`:
+///
+/// ```
+/// # use soehnle_t3005::{send, Command, Terminator};
+/// let mut buf = Vec::new();
+/// send(&mut buf, &Command::Tare, Terminator::CrLf).unwrap();
+/// assert_eq!(buf, b"<T>\r\n");
+/// ```
+pub fn send<W: Write>(w: &mut W, cmd: &impl ToAsciiString, terminator: Terminator) -> Result<()> {
+    write_frame(w, cmd.to_ascii_string()?.as_bytes(), terminator)
+}
+
+fn write_frame<W: Write>(w: &mut W, frame: &[u8], terminator: Terminator) -> Result<()> {
+    let mut bytes = Vec::with_capacity(frame.len() + 2);
+    bytes.extend_from_slice(frame);
+    bytes.extend_from_slice(terminator.as_bytes());
+    w.write_all(&bytes)?;
+    w.flush()?;
+    Ok(())
+}
+
+/// A command that can be sent with [`Terminal::send`].
+///
+/// The type decides how the response is handled: a [`Command`] isn't answered,
+/// a [`WithAck<Command>`] is only sent successfully once the terminal acknowledged it.
+pub trait Request: ToAsciiString + Copy + sealed::Sealed {
+    /// Whether the terminal answers with ACK or NAK.
+    const EXPECTS_ACK: bool;
+}
+
+impl Request for Command {
+    const EXPECTS_ACK: bool = false;
+}
+
+impl Request for WithAck<Command> {
+    const EXPECTS_ACK: bool = true;
+}
+
+mod sealed {
+    use crate::{Command, WithAck};
+
+    pub trait Sealed {}
+
+    impl Sealed for Command {}
+
+    impl Sealed for WithAck<Command> {}
+}
+
+/// The shortest pause between the queries of [`Terminal::read_best_effort`].
+const MIN_BEST_EFFORT_PAUSE: Duration = Duration::from_millis(10);
+
+/// Decides whether and how often a failed operation is attempted again.
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    /// Total number of attempts, including the first one.
+    pub max_attempts: u8,
+    /// Time to wait before the next attempt.
+    pub backoff: Duration,
+    /// Returns `true` for errors that are worth another attempt.
+    pub retry_on: fn(&Error) -> bool,
+}
+
+impl RetryPolicy {
+    /// Never retry.
+    #[must_use]
+    pub const fn none() -> Self {
+        Self::new(1, Duration::ZERO)
+    }
+
+    /// Retry [retryable](Error::is_retryable) errors
+    /// until `max_attempts` attempts have been made.
+    #[must_use]
+    pub const fn new(max_attempts: u8, backoff: Duration) -> Self {
+        Self {
+            max_attempts,
+            backoff,
+            retry_on: Error::is_retryable,
+        }
+    }
+
+    fn run<P, C: Clock, T>(
+        &self,
+        terminal: &mut Terminal<P, C>,
+        mut op: impl FnMut(&mut Terminal<P, C>) -> Result<T>,
+    ) -> Result<T> {
+        let mut attempt = 1;
+        loop {
+            match op(terminal) {
+                Err(err) if attempt < self.max_attempts && (self.retry_on)(&err) => {
+                    attempt += 1;
+                    if !self.backoff.is_zero() {
+                        terminal.clock.sleep(self.backoff);
+                    }
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::none()
+    }
+}
+
+/// Settings of a [`Terminal`].
+#[derive(Debug, Clone, Default)]
+pub struct TerminalConfig {
+    pub profile: DeviceProfile,
+    /// Format of the received frames.
+    pub protocol: Protocol,
+    /// Applied to commands sent with ACK and to queries.
+    pub retry: RetryPolicy,
+    /// Appended to every outgoing frame.
+    pub terminator: Terminator,
+    /// Pause between two bytes of an outgoing frame.
+    pub inter_byte_delay: Duration,
+    /// Minimum pause between two outgoing frames.
+    pub inter_command_delay: Duration,
+    /// Sends [`Protocol::enq`] after each query, for terminals
+    /// that only transmit when they are enquired.
+    ///
+    /// This is the default, [`Terminal::query_with_enquire`] decides per query.
+    pub enquire: bool,
+}
+
+/// State of the link to the terminal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkState {
+    /// The terminal responds.
+    Online,
+    /// The terminal didn't respond or the port failed.
+    Offline,
+}
+
+/// A terminal connected via a byte stream (usually a serial port).
+///
+/// The port should be configured with a read timeout,
+/// otherwise reading a missing response blocks forever.
+///
+/// All delays and timeouts are measured with the [`Clock`] `C`,
+/// see [`Terminal::with_clock`].
+#[derive(Debug)]
+pub struct Terminal<P, C = SystemClock> {
+    port: P,
+    config: TerminalConfig,
+    buf: FrameBuffer,
+    last_write: Option<Instant>,
+    clock: C,
+}
+
+impl<P: Read + Write> Terminal<P> {
+    pub fn new(port: P) -> Self {
+        Self::with_config(port, TerminalConfig::default())
+    }
+
+    /// Adopts an already opened and configured port, e.g. a
+    /// `Box<dyn serialport::SerialPort>` that is managed elsewhere.
+    ///
+    /// The terminal owns the port until it is returned by [`Terminal::into_inner`];
+    /// to share a port, pass a type that implements [`Read`] and [`Write`]
+    /// for a shared handle. The port is never reconfigured by the terminal.
+    pub const fn with_config(port: P, config: TerminalConfig) -> Self {
+        Self {
+            port,
+            config,
+            buf: FrameBuffer::new(),
+            last_write: None,
+            clock: SystemClock,
+        }
+    }
+}
+
+impl<P: Read + Write, C: Clock> Terminal<P, C> {
+    /// Replaces the clock, e.g. with a [`MockClock`](crate::MockClock) in tests.
+    pub fn with_clock<T: Clock>(self, clock: T) -> Terminal<P, T> {
+        let Self {
+            port,
+            config,
+            buf,
+            last_write,
+            ..
+        } = self;
+        Terminal {
+            port,
+            config,
+            buf,
+            last_write: last_write.map(|_| clock.now()),
+            clock,
+        }
+    }
+
+    pub const fn config(&self) -> &TerminalConfig {
+        &self.config
+    }
+
+    pub fn into_inner(self) -> P {
+        self.port
+    }
+
+    /// The port, e.g. to check its settings.
+    pub const fn get_ref(&self) -> &P {
+        &self.port
+    }
+
+    /// The port, e.g. to change its settings.
+    ///
+    /// Reading from it directly bypasses the buffered, not yet processed bytes.
+    pub fn get_mut(&mut self) -> &mut P {
+        &mut self.port
+    }
+
+    /// Discards all received but not yet processed bytes.
+    ///
+    /// Call this before a fresh [`Query::Once`] to make sure that the response
+    /// is not a stale frame that was buffered while nobody was reading.
+    ///
+    /// This reads until the port reports that no more data is available
+    /// (end of stream, a timeout or `WouldBlock`), so with a serial port
+    /// it takes at least its read timeout.
+    pub fn flush_input(&mut self) -> Result<()> {
+        self.buf.clear();
+        let mut chunk = [0; 64];
+        loop {
+            match self.port.read(&mut chunk) {
+                Ok(0) => return Ok(()),
+                Ok(_) => {}
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err)
+                    if matches!(
+                        err.kind(),
+                        io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock
+                    ) =>
+                {
+                    return Ok(());
+                }
+                Err(err) => return Err(err.into()),
+            }
+        }
+    }
+
+    /// Reads and discards everything received within `duration`
+    /// and returns the number of discarded bytes.
+    ///
+    /// Unlike [`Terminal::flush_input`] this doesn't stop at the first
+    /// read timeout, so it also catches frames that were already on their way,
+    /// e.g. after stopping a terminal that transmits continuously.
+    /// It returns early at the end of the stream.
+    pub fn drain(&mut self, duration: Duration) -> Result<usize> {
+        let deadline = self.clock.now() + duration;
+        let mut discarded = self.buf.len();
+        self.buf.clear();
+        let mut chunk = [0; 64];
+        while self.clock.now() < deadline {
+            match self.port.read(&mut chunk) {
+                Ok(0) => break,
+                Ok(n) => discarded += n,
+                Err(err)
+                    if matches!(
+                        err.kind(),
+                        io::ErrorKind::Interrupted
+                            | io::ErrorKind::TimedOut
+                            | io::ErrorKind::WouldBlock
+                    ) => {}
+                Err(err) => return Err(err.into()),
+            }
+        }
+        Ok(discarded)
+    }
+
+    /// Sends a command.
+    ///
+    /// A [`Command`] is sent without waiting for a response,
+    /// for a [`WithAck<Command>`] the ACK is awaited (see [`Terminal::send_with_ack`]).
+    pub fn send<R: Request>(&mut self, request: R) -> Result<()> {
+        let frame = request.to_ascii_string_with(&self.config.profile)?;
+        if !R::EXPECTS_ACK {
+            return self.write_frame(&frame);
+        }
+        let retry = self.config.retry;
+        retry.run(self, |terminal| {
+            terminal.write_frame(&frame)?;
+            match terminal.read_response()? {
+                Response::Ack => Ok(()),
+                Response::Nak => Err(Error::Nak),
+                Response::Message(_) | Response::StatusOnly { .. } => Err(Error::UnexpectedMessage),
+                Response::Echo(_) => Err(Error::UnexpectedEcho),
+            }
+        })
+    }
+
+    /// Sends a command and waits for the terminal to acknowledge it.
+    ///
+    /// A NAK is reported as [`Error::Nak`].
+    pub fn send_with_ack(&mut self, cmd: Command) -> Result<()> {
+        self.send(cmd.with_ack())
+    }
+
+    /// Sends [`Command::SetTare`] with ACK and checks that the tare echoed by the terminal
+    /// is the requested one, e.g. that it wasn't clamped near the capacity limit.
+    ///
+    /// This requires a terminal that echoes commands (see [`Protocol::echo`]).
+    /// A different tare is reported as [`Error::TareMismatch`] (after the ACK),
+    /// a missing echo as [`Error::UnexpectedAck`].
+    /// Like with [`Terminal::send_with_ack`], a NAK or a timeout is retried
+    /// according to the [`RetryPolicy`], with a fresh echo.
+    pub fn set_tare_verified(&mut self, tare: TareValue) -> Result<()> {
+        let frame = Command::SetTare(tare)
+            .with_ack()
+            .to_ascii_string_with(&self.config.profile)?;
+        let retry = self.config.retry;
+        let echoed = retry.run(self, |terminal| {
+            terminal.write_frame(&frame)?;
+            let mut echoed = None;
+            loop {
+                let frame = terminal
+                    .buf
+                    .read_frame(&mut terminal.port, &terminal.config.protocol)?;
+                let config = &terminal.config;
+                match parse_response(&config.protocol, &config.profile, &frame)? {
+                    Response::Echo(Echo::Command(Command::SetTare(value))) => echoed = Some(value),
+                    Response::Echo(_) => return Err(Error::UnexpectedEcho),
+                    Response::Ack => return Ok(echoed),
+                    Response::Nak => return Err(Error::Nak),
+                    Response::Message(_) | Response::StatusOnly { .. } => {
+                        return Err(Error::UnexpectedMessage)
+                    }
+                }
+            }
+        })?;
+        match echoed {
+            Some(value) if value == tare => Ok(()),
+            Some(value) => Err(Error::TareMismatch {
+                requested: tare.get(),
+                echoed: value.get(),
+            }),
+            None => Err(Error::UnexpectedAck),
+        }
+    }
+
+    /// Sends a bare ACK byte ([`Protocol::ack`]), e.g. to confirm the receipt of a message.
+    pub fn send_ack(&mut self) -> Result<()> {
+        self.write_control(self.config.protocol.ack)
+    }
+
+    /// Sends a bare NAK byte ([`Protocol::nak`]), e.g. to request a message again.
+    pub fn send_nak(&mut self) -> Result<()> {
+        self.write_control(self.config.protocol.nak)
+    }
+
+    /// Sends a bare ENQ byte ([`Protocol::enq`]) to make the terminal transmit.
+    pub fn send_enq(&mut self) -> Result<()> {
+        self.write_control(self.config.protocol.enq)
+    }
+
+    /// Sends all commands of the sequence with ACK, one after another,
+    /// and returns the response to each of them.
+    ///
+    /// Nothing is sent if any of the commands can't be encoded.
+    /// A NAK does not abort the sequence.
+    pub fn send_sequence(&mut self, seq: &CommandSequence) -> Result<Vec<Response>> {
+        let frames = seq
+            .0
+            .iter()
+            .map(|cmd| cmd.with_ack().to_ascii_string_with(&self.config.profile))
+            .collect::<Result<Vec<_>>>()?;
+        frames
+            .iter()
+            .map(|frame| {
+                self.write_frame(frame)?;
+                self.read_response()
+            })
+            .collect()
+    }
+
+    /// Requests a single message.
+    ///
+    /// An ENQ follows the request if [`TerminalConfig::enquire`] is set.
+    pub fn query(&mut self, query: Query) -> Result<Message> {
+        self.query_with_enquire(query, self.config.enquire)
+    }
+
+    /// Like [`Terminal::query`] but decides for this query
+    /// whether an ENQ ([`Protocol::enq`]) follows the request.
+    pub fn query_with_enquire(&mut self, query: Query, enquire: bool) -> Result<Message> {
+        let frame = query.to_ascii_string_with(&self.config.profile)?;
+        let retry = self.config.retry;
+        retry.run(self, |terminal| {
+            terminal.write_query_with(&frame, enquire)?;
+            reply_message(terminal.read_response()?)
+        })
+    }
+
+    /// Like [`Terminal::query`] but also returns the raw bytes
+    /// the message was parsed from.
+    ///
+    /// The bytes are trimmed like the parsed text: without padding
+    /// in front and without the terminating CR/LF.
+    ///
+    /// If the response can't be parsed, the raw bytes are
+    /// available in the returned [`Error::Frame`].
+    pub fn query_with_raw(&mut self, query: Query) -> Result<(Message, Vec<u8>)> {
+        let frame = query.to_ascii_string_with(&self.config.profile)?;
+        let retry = self.config.retry;
+        retry.run(self, |terminal| {
+            terminal.write_query(&frame)?;
+            let raw = trim_frame(terminal.read_raw_frame()?);
+            match parse_response(&terminal.config.protocol, &terminal.config.profile, &raw) {
+                Ok(response) => reply_message(response).map(|msg| (msg, raw)),
+                Err(err) => Err(Error::Frame {
+                    raw,
+                    source: Box::new(err),
+                }),
+            }
+        })
+    }
+
+    /// Queries the weight until the reading is stable or `timeout` has elapsed.
+    ///
+    /// Returns the stable weight and `true`, or after the timeout the last
+    /// valid but unstable weight and `false`. If there was no valid reading
+    /// at all, the last error is returned.
+    ///
+    /// An I/O error of the port, e.g. a disconnected device, ends the attempts
+    /// at once, unless [`RetryPolicy::retry_on`] classifies it as retryable;
+    /// the last valid weight is returned, or the error if there was none.
+    ///
+    /// Between two queries it waits for the [`RetryPolicy::backoff`],
+    /// at least 10 ms, so that a terminal that rejects the queries at once
+    /// isn't flooded with requests.
+    pub fn read_best_effort(&mut self, timeout: Duration) -> Result<(Weight, bool)> {
+        let deadline = self.clock.now() + timeout;
+        let mut last = Err(Error::Timeout);
+        loop {
+            match self.query(Query::Once) {
+                Ok(msg) => match msg.value_state() {
+                    ValueState::Weight(_) if msg.status.standstill => {
+                        return Ok((msg.weight(), true));
+                    }
+                    ValueState::Weight(_) => last = Ok(msg.weight()),
+                    _ => {
+                        if last.is_err() {
+                            last = Err(Error::ValueOutOfRange(msg.weight()));
+                        }
+                    }
+                },
+                Err(err @ Error::Io(_)) if !(self.config.retry.retry_on)(&err) => {
+                    return last.or(Err(err)).map(|weight| (weight, false));
+                }
+                Err(err) => {
+                    if last.is_err() {
+                        last = Err(err);
+                    }
+                }
+            }
+            let now = self.clock.now();
+            if now >= deadline {
+                return last.map(|weight| (weight, false));
+            }
+            let pause = self.config.retry.backoff.max(MIN_BEST_EFFORT_PAUSE);
+            self.clock.sleep(pause.min(deadline - now));
+        }
+    }
+
+    /// Sends a [`Query::Once`] and measures the time from the end of the request
+    /// until the response was received, e.g. to monitor the link quality.
+    ///
+    /// Any response counts, even one that can't be parsed.
+    /// The request is not retried.
+    pub fn ping(&mut self) -> Result<Duration> {
+        let frame = Query::Once.to_ascii_string_with(&self.config.profile)?;
+        self.write_query(&frame)?;
+        let start = self.clock.now();
+        self.read_raw_frame()?;
+        Ok(self.clock.now().saturating_duration_since(start))
+    }
+
+    /// Checks whether the terminal responds to a [`Query::Once`].
+    ///
+    /// Any response counts, even one that can't be parsed.
+    pub fn check_link(&mut self) -> LinkState {
+        match self.query(Query::Once) {
+            Err(Error::Timeout | Error::Io(_)) => LinkState::Offline,
+            _ => LinkState::Online,
+        }
+    }
+
+    /// Checks the link every `interval` (see [`Terminal::check_link`])
+    /// and reports the result to `on_check`, until it returns
+    /// [`ControlFlow::Break`].
+    ///
+    /// This is meant for monitoring an otherwise idle terminal.
+    pub fn keepalive<F>(&mut self, interval: Duration, mut on_check: F)
+    where
+        F: FnMut(LinkState) -> ControlFlow<()>,
+    {
+        while on_check(self.check_link()).is_continue() {
+            self.clock.sleep(interval);
+        }
+    }
+
+    fn write_frame(&mut self, frame: &str) -> Result<()> {
+        self.write_frame_with(frame.as_bytes(), self.config.terminator)
+    }
+
+    fn write_query(&mut self, frame: &str) -> Result<()> {
+        self.write_query_with(frame, self.config.enquire)
+    }
+
+    fn write_query_with(&mut self, frame: &str, enquire: bool) -> Result<()> {
+        self.write_frame(frame)?;
+        if enquire {
+            self.send_enq()?;
+        }
+        Ok(())
+    }
+
+    /// Writes a control byte without terminator.
+    fn write_control(&mut self, byte: u8) -> Result<()> {
+        self.write_frame_with(&[byte], Terminator::None)
+    }
+
+    fn write_frame_with(&mut self, frame: &[u8], terminator: Terminator) -> Result<()> {
+        let TerminalConfig {
+            inter_byte_delay,
+            inter_command_delay,
+            ..
+        } = self.config;
+        if let Some(last_write) = self.last_write {
+            let elapsed = self.clock.now().saturating_duration_since(last_write);
+            if let Some(pause) = inter_command_delay.checked_sub(elapsed) {
+                self.clock.sleep(pause);
+            }
+        }
+        if inter_byte_delay.is_zero() {
+            write_frame(&mut self.port, frame, terminator)?;
+        } else {
+            let bytes = frame.iter().chain(terminator.as_bytes());
+            for (i, byte) in bytes.enumerate() {
+                if i > 0 {
+                    self.clock.sleep(inter_byte_delay);
+                }
+                self.port.write_all(&[*byte])?;
+                self.port.flush()?;
+            }
+        }
+        self.last_write = Some(self.clock.now());
+        Ok(())
+    }
+
+    /// Reads the next ACK, NAK or CR/LF terminated message.
+    fn read_response(&mut self) -> Result<Response> {
+        let frame = self.read_raw_frame()?;
+        parse_response(&self.config.protocol, &self.config.profile, &frame)
+    }
+
+    /// Reads the next frame, skipping echoed commands.
+    fn read_raw_frame(&mut self) -> Result<Vec<u8>> {
+        loop {
+            let frame = self.buf.read_frame(&mut self.port, &self.config.protocol)?;
+            let is_echo = frame.iter().find(|b| !is_padding(**b)) == Some(&b'<');
+            if !(self.config.protocol.echo && is_echo) {
+                return Ok(frame);
+            }
+        }
+    }
+}
+
+/// The message of the response to a query, a NAK means that the terminal rejected it.
+fn reply_message(response: Response) -> Result<Message> {
+    match response {
+        Response::Nak => Err(Error::Nak),
+        response => Message::try_from(response),
+    }
+}
+
+/// Removes the padding in front of a frame and its line break.
+fn trim_frame(mut frame: Vec<u8>) -> Vec<u8> {
+    let end = frame
+        .iter()
+        .rposition(|b| !matches!(b, b'\r' | b'\n'))
+        .map_or(0, |i| i + 1);
+    frame.truncate(end);
+    let start = frame
+        .iter()
+        .position(|b| !is_padding(*b))
+        .unwrap_or(frame.len());
+    frame.drain(..start);
+    frame
+}
+
+/// Parses a frame and rejects implausible messages.
+pub(crate) fn parse_response(
+    protocol: &Protocol,
+    profile: &DeviceProfile,
+    frame: &[u8],
+) -> Result<Response> {
+    match parse_frame(protocol, frame)? {
+        Response::Message(msg) => profile.check_plausible(msg).map(Response::Message),
+        response => Ok(response),
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::float_cmp)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use super::*;
+    use crate::{MockClock, Standstill, Unit, UnitField, ACK, NAK};
+
+    /// A port that replays canned input and records everything written.
+    #[derive(Default)]
+    struct MockPort {
+        input: VecDeque<u8>,
+        output: Vec<u8>,
+        writes: usize,
+        timeouts: usize,
+        /// Returned by every read after the timeouts.
+        error: Option<io::ErrorKind>,
+        /// Advanced on every read.
+        clock: Option<(MockClock, Duration)>,
+    }
+
+    impl MockPort {
+        fn with_input(input: &[u8]) -> Self {
+            Self {
+                input: input.iter().copied().collect(),
+                ..Self::default()
+            }
+        }
+
+        fn output(&self) -> &str {
+            str::from_utf8(&self.output).unwrap()
+        }
+    }
+
+    impl Read for MockPort {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if let Some((clock, latency)) = &self.clock {
+                clock.advance(*latency);
+            }
+            if self.timeouts > 0 {
+                self.timeouts -= 1;
+                return Err(io::ErrorKind::TimedOut.into());
+            }
+            if let Some(kind) = self.error {
+                return Err(kind.into());
+            }
+            self.input.read(buf)
+        }
+    }
+
+    impl Write for MockPort {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.writes += 1;
+            self.output.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn retrying(max_attempts: u8) -> TerminalConfig {
+        TerminalConfig {
+            retry: RetryPolicy::new(max_attempts, Duration::ZERO),
+            ..TerminalConfig::default()
+        }
+    }
+
+    #[test]
+    fn send_to_writer() {
+        let mut buf = Vec::new();
+        send(&mut buf, &Command::ClearTare, Terminator::None).unwrap();
+        assert_eq!(buf, b"<TC>");
+
+        let mut buf = Vec::new();
+        send(&mut buf, &Query::Once.with_ack(), Terminator::Cr).unwrap();
+        send(&mut buf, &Command::Tare, Terminator::Etx).unwrap();
+        assert_eq!(buf, b"<a>\r<T>\x03");
+
+        let mut buf = Vec::new();
+        let cmd = Command::SetTare(TareValue::new(1500).unwrap());
+        send(&mut buf, &cmd, Terminator::CrLf).unwrap();
+        assert_eq!(buf, b"<T0001500>\r\n");
+    }
+
+    #[test]
+    fn send_with_configured_terminator() {
+        let config = TerminalConfig {
+            terminator: Terminator::CrLf,
+            ..TerminalConfig::default()
+        };
+        let mut terminal = Terminal::with_config(MockPort::with_input(&[ACK]), config);
+        terminal.send(Command::Tare).unwrap();
+        terminal.send_with_ack(Command::Tare).unwrap();
+        assert_eq!(terminal.into_inner().output(), "<T>\r\n<t>\r\n");
+    }
+
+    #[test]
+    fn send_control_bytes() {
+        let config = TerminalConfig {
+            terminator: Terminator::CrLf,
+            ..TerminalConfig::default()
+        };
+        let mut terminal = Terminal::with_config(MockPort::default(), config);
+        terminal.send_ack().unwrap();
+        terminal.send_nak().unwrap();
+        terminal.send_enq().unwrap();
+        terminal.send(Command::Tare).unwrap();
+        assert_eq!(terminal.into_inner().output, b"\x06\x15\x05<T>\r\n");
+    }
+
+    #[test]
+    fn send_non_ascii_control_bytes() {
+        let config = TerminalConfig {
+            protocol: Protocol {
+                ack: 0x86,
+                nak: 0x95,
+                enq: 0x85,
+                ..Protocol::default()
+            },
+            ..TerminalConfig::default()
+        };
+        let mut terminal = Terminal::with_config(MockPort::default(), config);
+        terminal.send_ack().unwrap();
+        terminal.send_nak().unwrap();
+        terminal.send_enq().unwrap();
+        assert_eq!(terminal.into_inner().output, [0x86, 0x95, 0x85]);
+    }
+
+    #[test]
+    fn enquire_after_queries() {
+        let config = TerminalConfig {
+            enquire: true,
+            ..TerminalConfig::default()
+        };
+        let port = MockPort::with_input(b"000001N        1,5 kg\r\n\x06");
+        let mut terminal = Terminal::with_config(port, config);
+        assert_eq!(terminal.query(Query::Once).unwrap().value, 1.5);
+        terminal.send_with_ack(Command::Tare).unwrap();
+        assert_eq!(terminal.into_inner().output(), "<A>\x05<t>");
+
+        let port = MockPort::with_input(b"000001N  1 kg\r\n000001N  2 kg\r\n000001N  3 kg\r\n");
+        let mut terminal = Terminal::new(port);
+        terminal.query_with_enquire(Query::Once, true).unwrap();
+        terminal.query(Query::Once).unwrap();
+        terminal.query_with_enquire(Query::Once, false).unwrap();
+        assert_eq!(terminal.into_inner().output(), "<A>\x05<A><A>");
+    }
+
+    #[test]
+    fn send_with_delays() {
+        let config = TerminalConfig {
+            terminator: Terminator::Cr,
+            inter_byte_delay: Duration::from_millis(2),
+            inter_command_delay: Duration::from_millis(10),
+            ..TerminalConfig::default()
+        };
+        let clock = MockClock::new();
+        let mut terminal =
+            Terminal::with_config(MockPort::default(), config).with_clock(clock.clone());
+        terminal.send(Command::Tare).unwrap();
+        // 3 pauses between the 4 bytes
+        assert_eq!(clock.elapsed(), Duration::from_millis(6));
+        terminal.send(Command::Tare).unwrap();
+        assert_eq!(clock.elapsed(), Duration::from_millis(22));
+        clock.advance(Duration::from_secs(1));
+        terminal.send(Command::Tare).unwrap();
+        assert_eq!(clock.elapsed(), Duration::from_millis(1028));
+        let port = terminal.into_inner();
+        assert_eq!(port.output(), "<T>\r<T>\r<T>\r");
+        assert_eq!(port.writes, 12);
+    }
+
+    #[test]
+    fn access_the_port() {
+        let mut terminal = Terminal::new(MockPort::default());
+        terminal.get_mut().timeouts = 1;
+        assert_eq!(terminal.get_ref().timeouts, 1);
+        assert_eq!(terminal.query(Query::Once), Err(Error::Timeout));
+        assert_eq!(terminal.get_ref().output(), "<A>");
+    }
+
+    #[test]
+    fn send_without_ack() {
+        let mut terminal = Terminal::new(MockPort::default());
+        terminal.send(Command::Tare).unwrap();
+        assert_eq!(terminal.into_inner().output(), "<T>");
+    }
+
+    #[test]
+    fn send_with_ack() {
+        let mut terminal = Terminal::new(MockPort::with_input(&[ACK]));
+        terminal.send_with_ack(Command::ClearTare).unwrap();
+        assert_eq!(terminal.into_inner().output(), "<tC>");
+    }
+
+    #[test]
+    fn response_depends_on_request_type() {
+        let mut terminal = Terminal::new(MockPort::with_input(&[NAK, ACK]));
+        terminal.send(Command::Tare).unwrap();
+        assert_eq!(terminal.send(Command::Tare.with_ack()), Err(Error::Nak));
+        terminal.send(Command::ClearTare.with_ack()).unwrap();
+        let port = terminal.into_inner();
+        assert_eq!(port.output(), "<T><t><tC>");
+        assert!(port.input.is_empty());
+    }
+
+    #[test]
+    fn skip_echoed_commands() {
+        let config = TerminalConfig {
+            protocol: Protocol {
+                echo: true,
+                ..Protocol::default()
+            },
+            ..TerminalConfig::default()
+        };
+        let port = MockPort::with_input(b"<t>\x06<tC>\r\n\x15<A>000001N  1,5 kg\r\n");
+        let mut terminal = Terminal::with_config(port, config);
+        terminal.send_with_ack(Command::Tare).unwrap();
+        assert_eq!(terminal.send_with_ack(Command::ClearTare), Err(Error::Nak));
+        assert_eq!(terminal.query(Query::Once).unwrap().value, 1.5);
+
+        // Without `echo` a `<` doesn't start a frame that ends at `>`
+        let port = MockPort::with_input(b"<t>\r\n\x06");
+        let mut terminal = Terminal::new(port);
+        assert!(matches!(
+            terminal.send_with_ack(Command::Tare),
+            Err(Error::MessageLength(_))
+        ));
+    }
+
+    #[test]
+    fn verify_echoed_tare() {
+        let config = TerminalConfig {
+            protocol: Protocol {
+                echo: true,
+                ..Protocol::default()
+            },
+            ..TerminalConfig::default()
+        };
+        let tare = TareValue::new(1500).unwrap();
+        let verify = |input: &[u8]| {
+            let mut terminal = Terminal::with_config(MockPort::with_input(input), config.clone());
+            let result = terminal.set_tare_verified(tare);
+            assert_eq!(terminal.into_inner().output(), "<t0001500>");
+            result
+        };
+        assert_eq!(verify(b"<t0001500>\x06"), Ok(()));
+        assert_eq!(
+            verify(b"<t0009999>\r\n\x06"),
+            Err(Error::TareMismatch {
+                requested: 1500,
+                echoed: 9999
+            })
+        );
+        assert_eq!(verify(b"<t0001500>\x15"), Err(Error::Nak));
+        assert_eq!(verify(b"\x06"), Err(Error::UnexpectedAck));
+        assert_eq!(verify(b"<T>\x06"), Err(Error::UnexpectedEcho));
+
+        let config = TerminalConfig {
+            protocol: config.protocol,
+            ..retrying(3)
+        };
+        let port = MockPort {
+            timeouts: 1,
+            ..MockPort::with_input(b"<t0001500>\x15<t0001500>\x06")
+        };
+        let mut terminal = Terminal::with_config(port, config);
+        assert_eq!(terminal.set_tare_verified(tare), Ok(()));
+        assert_eq!(
+            terminal.into_inner().output(),
+            "<t0001500><t0001500><t0001500>"
+        );
+    }
+
+    #[test]
+    fn remapped_control_bytes() {
+        let config = TerminalConfig {
+            protocol: Protocol {
+                ack: b'Y',
+                nak: b'N',
+                ..Protocol::default()
+            },
+            ..TerminalConfig::default()
+        };
+        let port = MockPort::with_input(b"YN");
+        let mut terminal = Terminal::with_config(port, config);
+        terminal.send_with_ack(Command::Tare).unwrap();
+        assert_eq!(terminal.send_with_ack(Command::Tare), Err(Error::Nak));
+        terminal.send_ack().unwrap();
+        terminal.send_nak().unwrap();
+        assert_eq!(terminal.into_inner().output(), "<t><t>YN");
+
+        let config = TerminalConfig {
+            protocol: Protocol {
+                ack: 0x86,
+                nak: 0x95,
+                ..Protocol::default()
+            },
+            ..TerminalConfig::default()
+        };
+        let port = MockPort::with_input(b"\x86\r\n\x95");
+        let mut terminal = Terminal::with_config(port, config);
+        terminal.send_with_ack(Command::Tare).unwrap();
+        assert_eq!(terminal.send_with_ack(Command::Tare), Err(Error::Nak));
+        terminal.send_ack().unwrap();
+        assert_eq!(terminal.into_inner().output, b"<t><t>\x86");
+    }
+
+    #[test]
+    fn framed_responses() {
+        let config = TerminalConfig {
+            protocol: Protocol {
+                framing_bytes: vec![0x02, 0x03],
+                ..Protocol::default()
+            },
+            ..TerminalConfig::default()
+        };
+        let port = MockPort::with_input(b"\x02\x06\x03\x02\x15\x03\x02000001N        3,5 kg\x03");
+        let mut terminal = Terminal::with_config(port, config);
+        terminal.send_with_ack(Command::Tare).unwrap();
+        assert_eq!(terminal.send_with_ack(Command::Tare), Err(Error::Nak));
+        assert_eq!(terminal.query(Query::Once), Ok(Message::new(1, 3.5)));
+    }
+
+    #[test]
+    fn send_with_padded_ack() {
+        let mut terminal = Terminal::new(MockPort::with_input(&[0, ACK, 0, b' ', NAK]));
+        terminal.send_with_ack(Command::Tare).unwrap();
+        assert_eq!(terminal.send_with_ack(Command::Tare), Err(Error::Nak));
+    }
+
+    #[test]
+    fn nak_is_an_error_without_retry() {
+        let mut terminal = Terminal::new(MockPort::with_input(&[NAK, ACK]));
+        assert_eq!(terminal.send_with_ack(Command::Tare), Err(Error::Nak));
+        assert_eq!(terminal.into_inner().output(), "<t>");
+    }
+
+    #[test]
+    fn nak_triggers_retry() {
+        let port = MockPort::with_input(&[NAK, NAK, ACK]);
+        let mut terminal = Terminal::with_config(port, retrying(3));
+        terminal.send_with_ack(Command::Tare).unwrap();
+        assert_eq!(terminal.into_inner().output(), "<t><t><t>");
+
+        let port = MockPort::with_input(&[NAK, NAK, ACK]);
+        let mut terminal = Terminal::with_config(port, retrying(2));
+        assert_eq!(terminal.send_with_ack(Command::Tare), Err(Error::Nak));
+        assert_eq!(terminal.into_inner().output(), "<t><t>");
+    }
+
+    #[test]
+    fn timeout_triggers_retry() {
+        let port = MockPort {
+            timeouts: 1,
+            ..MockPort::with_input(&[ACK])
+        };
+        let mut terminal = Terminal::with_config(port, retrying(2));
+        terminal.send_with_ack(Command::Tare).unwrap();
+        assert_eq!(terminal.into_inner().output(), "<t><t>");
+    }
+
+    #[test]
+    fn busy_then_ready() {
+        let protocol = Protocol {
+            busy: Some(0x13),
+            ..Protocol::default()
+        };
+        let input = [0x13, ACK];
+        let config = TerminalConfig {
+            protocol: protocol.clone(),
+            ..TerminalConfig::default()
+        };
+        let mut terminal = Terminal::with_config(MockPort::with_input(&input), config);
+        assert_eq!(
+            terminal.send_with_ack(Command::Tare),
+            Err(Error::DeviceBusy)
+        );
+        assert_eq!(terminal.send_with_ack(Command::Tare), Ok(()));
+
+        let config = TerminalConfig {
+            protocol,
+            retry: RetryPolicy::new(2, Duration::from_secs(1)),
+            ..TerminalConfig::default()
+        };
+        let clock = MockClock::new();
+        let mut terminal =
+            Terminal::with_config(MockPort::with_input(&input), config).with_clock(clock.clone());
+        terminal.send_with_ack(Command::Tare).unwrap();
+        assert_eq!(clock.elapsed(), Duration::from_secs(1));
+        assert_eq!(terminal.into_inner().output(), "<t><t>");
+    }
+
+    #[test]
+    fn wait_for_backoff() {
+        let port = MockPort {
+            timeouts: 2,
+            ..MockPort::with_input(&[ACK])
+        };
+        let config = TerminalConfig {
+            retry: RetryPolicy::new(3, Duration::from_secs(5)),
+            ..TerminalConfig::default()
+        };
+        let clock = MockClock::new();
+        let mut terminal = Terminal::with_config(port, config).with_clock(clock.clone());
+        let start = Instant::now();
+        terminal.send_with_ack(Command::Tare).unwrap();
+        assert_eq!(clock.elapsed(), Duration::from_secs(10));
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn encoding_error_is_not_retried() {
+        let config = TerminalConfig {
+            profile: DeviceProfile {
+                tare_digits: 6,
+                ..DeviceProfile::default()
+            },
+            ..retrying(3)
+        };
+        let mut terminal = Terminal::with_config(MockPort::with_input(&[ACK]), config);
+        let cmd = Command::SetTare(TareValue::new(1_000_000).unwrap());
+        assert_eq!(
+            terminal.send_with_ack(cmd),
+            Err(Error::TareValue(1_000_000))
+        );
+        assert_eq!(terminal.into_inner().output(), "");
+    }
+
+    #[test]
+    fn send_command_sequence() {
+        let seq = CommandSequence(vec![Command::ClearTare, Command::Tare]);
+        let mut terminal = Terminal::new(MockPort::with_input(&[ACK, NAK]));
+        assert_eq!(
+            terminal.send_sequence(&seq).unwrap(),
+            [Response::Ack, Response::Nak]
+        );
+        assert_eq!(terminal.into_inner().output(), "<tC><t>");
+    }
+
+    #[test]
+    fn invalid_command_sequence_is_not_sent() {
+        let config = TerminalConfig {
+            profile: DeviceProfile {
+                tare_digits: 6,
+                ..DeviceProfile::default()
+            },
+            ..TerminalConfig::default()
+        };
+        let seq = CommandSequence(vec![
+            Command::Tare,
+            Command::SetTare(TareValue::new(1_000_000).unwrap()),
+        ]);
+        let mut terminal = Terminal::with_config(MockPort::with_input(&[ACK, ACK]), config);
+        assert_eq!(
+            terminal.send_sequence(&seq),
+            Err(Error::TareValue(1_000_000))
+        );
+        assert_eq!(terminal.into_inner().output(), "");
+    }
+
+    #[test]
+    fn query_message() {
+        let port = MockPort::with_input(b"000101N        3,1 kg\r\n");
+        let mut terminal = Terminal::new(port);
+        let msg = terminal.query(Query::Once).unwrap();
+        assert_eq!(msg.id, 1);
+        assert_eq!(terminal.into_inner().output(), "<A>");
+    }
+
+    #[test]
+    fn query_with_configured_protocol() {
+        let config = TerminalConfig {
+            protocol: Protocol {
+                unit: UnitField::Absent(Unit::Gram),
+                ..Protocol::default()
+            },
+            ..TerminalConfig::default()
+        };
+        let port = MockPort::with_input(b"000101N        250\r\n");
+        let mut terminal = Terminal::with_config(port, config);
+        let msg = terminal.query(Query::Once).unwrap();
+        assert_eq!(msg.weight(), Weight::grams(250.0));
+    }
+
+    #[test]
+    fn query_rejects_implausible_weight() {
+        let config = TerminalConfig {
+            profile: DeviceProfile {
+                max_plausible_weight: Some(Weight::kilograms(50.0)),
+                ..DeviceProfile::default()
+            },
+            ..TerminalConfig::default()
+        };
+        let port = MockPort::with_input(b"000101N   900000,0 kg\r\n000101N       49,5 kg\r\n");
+        let mut terminal = Terminal::with_config(port, config);
+        assert_eq!(
+            terminal.query(Query::Once),
+            Err(Error::ValueOutOfRange(Weight::kilograms(900_000.0)))
+        );
+        assert_eq!(terminal.query(Query::Once).unwrap().value, 49.5);
+    }
+
+    #[test]
+    fn query_with_embedded_line_break() {
+        let config = TerminalConfig {
+            protocol: Protocol {
+                standstill: Standstill::Symbol('*'),
+                strip_line_breaks: true,
+                ..Protocol::default()
+            },
+            ..TerminalConfig::default()
+        };
+        let port = MockPort::with_input(b"000101N       3,1 kg\r*\r\n");
+        let mut terminal = Terminal::with_config(port, config);
+        let msg = terminal.query(Query::Once).unwrap();
+        assert!(msg.status.standstill);
+        assert_eq!(msg.weight(), Weight::kilograms(3.1));
+    }
+
+    #[test]
+    fn query_retries_timeout_but_not_parse_errors() {
+        let port = MockPort {
+            timeouts: 1,
+            ..MockPort::with_input(b"000002N        3,1 kg\r\n")
+        };
+        let mut terminal = Terminal::with_config(port, retrying(2));
+        assert_eq!(terminal.query(Query::Once).unwrap().id, 2);
+
+        let port = MockPort::with_input(b"0000XXN        3,1 kg\r\n000002N        3,1 kg\r\n");
+        let mut terminal = Terminal::with_config(port, retrying(3));
+        assert!(matches!(
+            terminal.query(Query::Once),
+            Err(Error::BalanceId(id, _)) if id == "XX"
+        ));
+        assert_eq!(terminal.into_inner().output(), "<A>");
+    }
+
+    #[test]
+    fn read_until_stable() {
+        let port = MockPort::with_input(
+            b"000001N        3,1 kg\r\n000001N        3,2 kg\r\n001001N        3,3 kg\r\n",
+        );
+        let mut terminal = Terminal::new(port);
+        let timeout = Duration::from_secs(10);
+        assert_eq!(
+            terminal.read_best_effort(timeout),
+            Ok((Weight::kilograms(3.3), true))
+        );
+        assert_eq!(terminal.into_inner().output(), "<A><A><A>");
+    }
+
+    #[test]
+    fn read_best_effort_after_timeout() {
+        let port = MockPort::with_input(b"000001N        3,1 kg\r\n000001N        3,2 kg\r\n");
+        let mut terminal = Terminal::new(port);
+        let timeout = Duration::from_millis(10);
+        assert_eq!(
+            terminal.read_best_effort(timeout),
+            Ok((Weight::kilograms(3.2), false))
+        );
+
+        let port = MockPort::with_input(b"011001N     9999,9 kg\r\n");
+        let mut terminal = Terminal::new(port);
+        assert_eq!(
+            terminal.read_best_effort(Duration::ZERO),
+            Err(Error::ValueOutOfRange(Weight::kilograms(9999.9)))
+        );
+
+        let mut terminal = Terminal::new(MockPort::default());
+        assert_eq!(
+            terminal.read_best_effort(timeout),
+            Err(Error::Io(io::ErrorKind::UnexpectedEof))
+        );
+    }
+
+    #[test]
+    fn read_best_effort_stops_at_port_errors() {
+        let port = MockPort {
+            error: Some(io::ErrorKind::BrokenPipe),
+            ..MockPort::default()
+        };
+        let mut terminal = Terminal::new(port);
+        assert_eq!(
+            terminal.read_best_effort(Duration::from_secs(60)),
+            Err(Error::Io(io::ErrorKind::BrokenPipe))
+        );
+        assert_eq!(terminal.into_inner().writes, 1);
+
+        // Timeouts and garbage are read again
+        let port = MockPort {
+            timeouts: 1,
+            input: b"0000XXN        3,1 kg\r\n000001N        3,2 kg\r\n"
+                .iter()
+                .copied()
+                .collect(),
+            ..MockPort::default()
+        };
+        let mut terminal = Terminal::new(port);
+        assert_eq!(
+            terminal.read_best_effort(Duration::from_secs(60)),
+            Ok((Weight::kilograms(3.2), false))
+        );
+    }
+
+    #[test]
+    fn read_best_effort_pauses_between_queries() {
+        let clock = MockClock::new();
+        let port = MockPort::with_input(&[NAK; 1000]);
+        let mut terminal = Terminal::new(port).with_clock(clock.clone());
+        assert_eq!(
+            terminal.read_best_effort(Duration::from_secs(1)),
+            Err(Error::Nak)
+        );
+        assert_eq!(clock.elapsed(), Duration::from_secs(1));
+        assert_eq!(terminal.into_inner().writes, 101);
+
+        let config = TerminalConfig {
+            retry: RetryPolicy::new(1, Duration::from_millis(250)),
+            ..TerminalConfig::default()
+        };
+        let clock = MockClock::new();
+        let port = MockPort::with_input(&[NAK; 1000]);
+        let mut terminal = Terminal::with_config(port, config).with_clock(clock.clone());
+        assert_eq!(
+            terminal.read_best_effort(Duration::from_secs(1)),
+            Err(Error::Nak)
+        );
+        assert_eq!(terminal.into_inner().writes, 5);
+    }
+
+    #[test]
+    fn read_best_effort_with_mock_clock() {
+        let input: Vec<u8> = (1..=9)
+            .flat_map(|i| format!("000001N        3,{i} kg\r\n").into_bytes())
+            .collect();
+        let config = TerminalConfig {
+            inter_command_delay: Duration::from_secs(20),
+            ..TerminalConfig::default()
+        };
+        let clock = MockClock::new();
+        let mut terminal =
+            Terminal::with_config(MockPort::with_input(&input), config).with_clock(clock.clone());
+        let start = Instant::now();
+        assert_eq!(
+            terminal.read_best_effort(Duration::from_secs(60)),
+            Ok((Weight::kilograms(3.4), false))
+        );
+        assert_eq!(clock.elapsed(), Duration::from_secs(60));
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn query_with_raw_bytes() {
+        let port = MockPort::with_input(b"000101N        3,1 kg\r\n000102N  2 kg\r\n");
+        let mut terminal = Terminal::new(port);
+        let (msg, raw) = terminal.query_with_raw(Query::Once).unwrap();
+        assert_eq!(msg.id, 1);
+        assert_eq!(raw, b"000101N        3,1 kg");
+        // The LF of the previous frame isn't part of the next one
+        let (msg, raw) = terminal.query_with_raw(Query::Once).unwrap();
+        assert_eq!(msg.id, 2);
+        assert_eq!(raw, b"000102N  2 kg");
+
+        let port = MockPort::with_input(b"0000XXN        3,1 kg\r\n");
+        let mut terminal = Terminal::new(port);
+        assert_eq!(
+            terminal.query_with_raw(Query::Once),
+            Err(Error::Frame {
+                raw: b"0000XXN        3,1 kg".to_vec(),
+                source: Box::new(Error::BalanceId(
+                    "XX".into(),
+                    "XX".parse::<u8>().unwrap_err()
+                ))
+            })
+        );
+
+        let mut terminal = Terminal::new(MockPort::with_input(&[NAK]));
+        assert_eq!(terminal.query_with_raw(Query::Once), Err(Error::Nak));
+    }
+
+    #[test]
+    fn flush_stale_input() {
+        let port = MockPort::with_input(
+            b"000001N        1,0 kg\r\n000001N        2,0 kg\r\n000001N        3,0 kg",
+        );
+        let mut terminal = Terminal::new(port);
+        terminal.flush_input().unwrap();
+        assert_eq!(
+            terminal.query(Query::Once),
+            Err(Error::Io(io::ErrorKind::UnexpectedEof))
+        );
+        assert!(terminal.into_inner().input.is_empty());
+
+        let port = MockPort {
+            timeouts: 1,
+            ..MockPort::with_input(b"000001N        1,0 kg\r\n")
+        };
+        let mut terminal = Terminal::new(port);
+        terminal.flush_input().unwrap();
+        assert_eq!(terminal.query(Query::Once).unwrap().value, 1.0);
+    }
+
+    #[test]
+    fn drain_continuous_stream() {
+        /// Sends a frame every 100 ms.
+        struct ContinuousPort(MockClock);
+
+        impl Read for ContinuousPort {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                self.0.advance(Duration::from_millis(100));
+                let frame = b"000001N        1,0 kg\r\n";
+                buf[..frame.len()].copy_from_slice(frame);
+                Ok(frame.len())
+            }
+        }
+
+        impl Write for ContinuousPort {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                Ok(buf.len())
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let clock = MockClock::new();
+        let mut terminal = Terminal::new(ContinuousPort(clock.clone())).with_clock(clock.clone());
+        assert_eq!(terminal.drain(Duration::from_secs(1)), Ok(230));
+        assert_eq!(clock.elapsed(), Duration::from_secs(1));
+
+        let port = MockPort {
+            timeouts: 2,
+            ..MockPort::with_input(b"000001N        1,0 kg\r\n000001N        2,0 kg\r\n")
+        };
+        let mut terminal = Terminal::new(port);
+        assert_eq!(terminal.drain(Duration::from_secs(1)), Ok(46));
+        assert_eq!(
+            terminal.query(Query::Once),
+            Err(Error::Io(io::ErrorKind::UnexpectedEof))
+        );
+    }
+
+    #[test]
+    fn ping_measures_latency() {
+        let clock = MockClock::new();
+        let port = MockPort {
+            clock: Some((clock.clone(), Duration::from_millis(30))),
+            timeouts: 1,
+            ..MockPort::with_input(b"0000XXN\r\n")
+        };
+        let config = TerminalConfig {
+            inter_command_delay: Duration::from_secs(1),
+            ..TerminalConfig::default()
+        };
+        let mut terminal = Terminal::with_config(port, config).with_clock(clock.clone());
+        assert_eq!(terminal.ping(), Err(Error::Timeout));
+        assert_eq!(terminal.ping(), Ok(Duration::from_millis(30)));
+        assert_eq!(
+            terminal.ping(),
+            Err(Error::Io(io::ErrorKind::UnexpectedEof))
+        );
+        assert_eq!(terminal.into_inner().output(), "<A><A><A>");
+    }
+
+    #[test]
+    fn keepalive() {
+        let port = MockPort {
+            timeouts: 1,
+            ..MockPort::with_input(b"000001N        1,0 kg\r\n0000XXN        1,0 kg\r\n")
+        };
+        let mut terminal = Terminal::new(port);
+        let mut states = vec![];
+        terminal.keepalive(Duration::ZERO, |state| {
+            states.push(state);
+            if states.len() < 4 {
+                ControlFlow::Continue(())
+            } else {
+                ControlFlow::Break(())
+            }
+        });
+        assert_eq!(
+            states,
+            [
+                LinkState::Offline,
+                LinkState::Online,
+                LinkState::Online,
+                LinkState::Offline
+            ]
+        );
+        assert_eq!(terminal.into_inner().output(), "<A><A><A><A>");
+    }
+
+    #[test]
+    fn missing_response() {
+        let mut terminal = Terminal::new(MockPort::default());
+        assert_eq!(
+            terminal.query(Query::Once),
+            Err(Error::Io(io::ErrorKind::UnexpectedEof))
+        );
+    }
+}
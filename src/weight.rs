@@ -0,0 +1,388 @@
+use std::{
+    fmt,
+    ops::{Add, AddAssign, Neg, Sub, SubAssign},
+    str::FromStr,
+};
+
+use crate::{DeviceProfile, Error, Result};
+
+/// Unit of a weight value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Unit {
+    #[default]
+    Kilogram,
+    Gram,
+    /// The international avoirdupois pound (0.453 592 37 kg).
+    Pound,
+}
+
+impl Unit {
+    /// The unit symbol as transmitted by the terminal.
+    #[must_use]
+    pub const fn symbol(self) -> &'static str {
+        match self {
+            Self::Kilogram => "kg",
+            Self::Gram => "g",
+            Self::Pound => "lb",
+        }
+    }
+
+    const fn grams(self) -> f64 {
+        match self {
+            Self::Kilogram => 1000.0,
+            Self::Gram => 1.0,
+            Self::Pound => 453.592_37,
+        }
+    }
+
+    /// Splits a trailing unit symbol off `s`.
+    pub(crate) fn split_suffix(s: &str) -> (&str, Option<Self>) {
+        // "kg" has to be checked before "g"
+        [Self::Kilogram, Self::Pound, Self::Gram]
+            .into_iter()
+            .find_map(|unit| s.strip_suffix(unit.symbol()).map(|rest| (rest, Some(unit))))
+            .unwrap_or((s, None))
+    }
+}
+
+impl FromStr for Unit {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self> {
+        match Self::split_suffix(s) {
+            ("", Some(unit)) => Ok(unit),
+            _ => Err(Error::Unit(s.into())),
+        }
+    }
+}
+
+impl fmt::Display for Unit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.symbol())
+    }
+}
+
+/// A weight value together with its unit.
+///
+/// Adding or subtracting weights of different units
+/// converts the right hand side into the unit of the left hand side:
+///
+/// ```
+/// # use soehnle_t3005::Weight;
+/// let total = Weight::kilograms(1.0) + Weight::grams(500.0);
+/// assert_eq!(total, Weight::kilograms(1.5));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Weight {
+    pub value: f32,
+    pub unit: Unit,
+}
+
+impl Weight {
+    #[must_use]
+    pub const fn new(value: f32, unit: Unit) -> Self {
+        Self { value, unit }
+    }
+
+    #[must_use]
+    pub const fn kilograms(value: f32) -> Self {
+        Self::new(value, Unit::Kilogram)
+    }
+
+    #[must_use]
+    pub const fn grams(value: f32) -> Self {
+        Self::new(value, Unit::Gram)
+    }
+
+    #[must_use]
+    pub const fn pounds(value: f32) -> Self {
+        Self::new(value, Unit::Pound)
+    }
+
+    /// Zero kilograms.
+    #[must_use]
+    pub const fn zero() -> Self {
+        Self::kilograms(0.0)
+    }
+
+    /// Converts the weight into the given unit.
+    #[must_use]
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn to_unit(self, unit: Unit) -> Self {
+        if self.unit == unit {
+            return self;
+        }
+        let value = f64::from(self.value) * self.unit.grams() / unit.grams();
+        Self::new(value as f32, unit)
+    }
+
+    /// The weight in kilograms.
+    #[must_use]
+    pub fn to_kilograms(self) -> f32 {
+        self.to_unit(Unit::Kilogram).value
+    }
+
+    /// Rounds the weight to the nearest step of the
+    /// [`DeviceProfile::display_resolution`], i.e. to what the display shows.
+    ///
+    /// The unit is kept. Without a resolution the weight is returned as is.
+    ///
+    /// ```
+    /// # use soehnle_t3005::{DeviceProfile, Weight};
+    /// let profile = DeviceProfile {
+    ///     display_resolution: Some(Weight::grams(5.0)),
+    ///     ..DeviceProfile::default()
+    /// };
+    /// assert_eq!(Weight::kilograms(1.2372).as_displayed(&profile), Weight::kilograms(1.235));
+    /// ```
+    #[must_use]
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn as_displayed(&self, profile: &DeviceProfile) -> Self {
+        let Some(step) = profile.display_resolution else {
+            return *self;
+        };
+        let step = f64::from(step.to_unit(self.unit).value);
+        if step <= 0.0 || !step.is_finite() {
+            return *self;
+        }
+        let steps = (f64::from(self.value) / step).round();
+        Self::new((steps * step) as f32, self.unit)
+    }
+
+    /// Whether the weight lies between `lo` and `hi` (inclusive).
+    ///
+    /// The bounds are converted into the unit of `self`:
+    ///
+    /// ```
+    /// # use soehnle_t3005::Weight;
+    /// let package = Weight::kilograms(1.005);
+    /// assert!(package.is_within(Weight::grams(990.0), Weight::grams(1010.0)));
+    /// ```
+    #[must_use]
+    pub fn is_within(self, lo: Self, hi: Self) -> bool {
+        let lo = lo.to_unit(self.unit).value;
+        let hi = hi.to_unit(self.unit).value;
+        (lo..=hi).contains(&self.value)
+    }
+}
+
+/// The net weight a terminal would show after taring `tare` with `gross` on the balance,
+/// e.g. to let an operator confirm a [`Command::SetTare`](crate::Command::SetTare).
+///
+/// The result is in the unit of `gross` and negative if the tare exceeds it.
+///
+/// ```
+/// # use soehnle_t3005::{preview_net, Weight};
+/// let net = preview_net(Weight::kilograms(12.5), Weight::grams(500.0));
+/// assert_eq!(net, Weight::kilograms(12.0));
+/// ```
+#[must_use]
+pub fn preview_net(gross: Weight, tare: Weight) -> Weight {
+    gross - tare
+}
+
+impl Default for Weight {
+    fn default() -> Self {
+        Self::zero()
+    }
+}
+
+impl fmt::Display for Weight {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.value, self.unit)
+    }
+}
+
+impl Add for Weight {
+    type Output = Self;
+    fn add(self, rhs: Self) -> Self {
+        Self::new(self.value + rhs.to_unit(self.unit).value, self.unit)
+    }
+}
+
+impl Sub for Weight {
+    type Output = Self;
+    fn sub(self, rhs: Self) -> Self {
+        Self::new(self.value - rhs.to_unit(self.unit).value, self.unit)
+    }
+}
+
+impl AddAssign for Weight {
+    fn add_assign(&mut self, rhs: Self) {
+        *self = *self + rhs;
+    }
+}
+
+impl SubAssign for Weight {
+    fn sub_assign(&mut self, rhs: Self) {
+        *self = *self - rhs;
+    }
+}
+
+impl Neg for Weight {
+    type Output = Self;
+    fn neg(self) -> Self {
+        Self::new(-self.value, self.unit)
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::float_cmp)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn zero() {
+        assert_eq!(Weight::zero().value, 0.0);
+        assert_eq!(Weight::zero().unit, Unit::Kilogram);
+        assert_eq!(Weight::default(), Weight::zero());
+        assert_eq!(
+            Weight::kilograms(2.5) + Weight::zero(),
+            Weight::kilograms(2.5)
+        );
+    }
+
+    #[test]
+    fn convert_units() {
+        assert_eq!(
+            Weight::kilograms(1.5).to_unit(Unit::Gram),
+            Weight::grams(1500.0)
+        );
+        assert_eq!(
+            Weight::grams(250.0).to_unit(Unit::Kilogram),
+            Weight::kilograms(0.25)
+        );
+        assert_eq!(Weight::grams(3.0).to_unit(Unit::Gram), Weight::grams(3.0));
+    }
+
+    #[test]
+    fn convert_pounds() {
+        assert_eq!(Weight::pounds(1.0).to_kilograms(), 0.453_592_37);
+        assert_eq!(Weight::pounds(10.0).to_kilograms(), 4.535_923_7);
+        assert_eq!(Weight::pounds(-2.5).to_kilograms(), -1.133_980_9);
+        assert_eq!(
+            Weight::kilograms(0.453_592_37).to_unit(Unit::Pound).value,
+            1.0
+        );
+        assert_eq!(Weight::grams(1500.0).to_kilograms(), 1.5);
+        assert_eq!(Weight::kilograms(1.5).to_kilograms(), 1.5);
+    }
+
+    #[test]
+    fn parse_unit() {
+        assert_eq!(Unit::from_str("kg").unwrap(), Unit::Kilogram);
+        assert_eq!(Unit::from_str("g").unwrap(), Unit::Gram);
+        assert_eq!(Unit::from_str("lb").unwrap(), Unit::Pound);
+        assert_eq!(Unit::from_str("t"), Err(Error::Unit("t".into())));
+        assert!(Unit::from_str("xkg").is_err());
+        assert!(Unit::from_str("").is_err());
+    }
+
+    #[test]
+    fn arithmetic_with_same_unit() {
+        let gross = Weight::kilograms(12.5);
+        let tare = Weight::kilograms(2.5);
+        assert_eq!(gross - tare, Weight::kilograms(10.0));
+        assert_eq!(gross + tare, Weight::kilograms(15.0));
+        assert_eq!(tare - gross, Weight::kilograms(-10.0));
+        assert_eq!(-tare, Weight::kilograms(-2.5));
+
+        let mut w = gross;
+        w -= tare;
+        assert_eq!(w, Weight::kilograms(10.0));
+        w += tare;
+        assert_eq!(w, gross);
+    }
+
+    #[test]
+    fn round_to_display_resolution() {
+        let profile = |resolution| DeviceProfile {
+            display_resolution: resolution,
+            ..DeviceProfile::default()
+        };
+        let grams_5 = profile(Some(Weight::grams(5.0)));
+        assert_eq!(
+            Weight::kilograms(1.2372).as_displayed(&grams_5),
+            Weight::kilograms(1.235)
+        );
+        assert_eq!(
+            Weight::kilograms(1.2378).as_displayed(&grams_5),
+            Weight::kilograms(1.24)
+        );
+        assert_eq!(
+            Weight::grams(-12.4).as_displayed(&grams_5),
+            Weight::grams(-10.0)
+        );
+        assert_eq!(
+            Weight::grams(1002.0).as_displayed(&grams_5),
+            Weight::grams(1000.0)
+        );
+        let kg_0_1 = profile(Some(Weight::kilograms(0.1)));
+        assert_eq!(
+            Weight::kilograms(2.349).as_displayed(&kg_0_1),
+            Weight::kilograms(2.3)
+        );
+        assert_eq!(
+            Weight::kilograms(2.349).as_displayed(&profile(None)),
+            Weight::kilograms(2.349)
+        );
+        assert_eq!(
+            Weight::kilograms(2.349).as_displayed(&profile(Some(Weight::zero()))),
+            Weight::kilograms(2.349)
+        );
+    }
+
+    #[test]
+    fn preview_net_weight() {
+        let gross = Weight::kilograms(12.5);
+        assert_eq!(
+            preview_net(gross, Weight::kilograms(2.5)),
+            Weight::kilograms(10.0)
+        );
+        assert_eq!(preview_net(gross, Weight::zero()), gross);
+        assert_eq!(
+            preview_net(Weight::grams(800.0), Weight::kilograms(1.0)),
+            Weight::grams(-200.0)
+        );
+    }
+
+    #[test]
+    fn arithmetic_with_mixed_units() {
+        assert_eq!(
+            Weight::kilograms(1.0) + Weight::grams(500.0),
+            Weight::kilograms(1.5)
+        );
+        assert_eq!(
+            Weight::grams(500.0) + Weight::kilograms(1.0),
+            Weight::grams(1500.0)
+        );
+        assert_eq!(
+            Weight::kilograms(2.0) - Weight::grams(250.0),
+            Weight::kilograms(1.75)
+        );
+    }
+
+    #[test]
+    fn within_bounds() {
+        let lo = Weight::grams(990.0);
+        let hi = Weight::grams(1010.0);
+        assert!(Weight::grams(990.0).is_within(lo, hi));
+        assert!(Weight::grams(1010.0).is_within(lo, hi));
+        assert!(Weight::grams(1000.0).is_within(lo, hi));
+        assert!(!Weight::grams(989.9).is_within(lo, hi));
+        assert!(!Weight::grams(1010.1).is_within(lo, hi));
+
+        assert!(Weight::kilograms(0.99).is_within(lo, hi));
+        assert!(Weight::kilograms(1.01).is_within(lo, hi));
+        assert!(!Weight::kilograms(1.02).is_within(lo, hi));
+        assert!(Weight::grams(1000.0).is_within(Weight::kilograms(1.0), hi));
+
+        assert!(!Weight::grams(1000.0).is_within(hi, lo));
+        assert!(!Weight::grams(f32::NAN).is_within(lo, hi));
+    }
+
+    #[test]
+    fn display() {
+        assert_eq!(Weight::kilograms(1.5).to_string(), "1.5 kg");
+        assert_eq!(Weight::grams(-20.0).to_string(), "-20 g");
+    }
+}